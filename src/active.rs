@@ -0,0 +1,163 @@
+use wasm_bindgen::prelude::*;
+
+use crate::{Cell, Topology};
+
+// the side of a tile of the dense grid
+const TILE: u32 = 32;
+
+// How much of the board the last tick worked out
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct TickStats {
+    tiles: u32,
+    evaluated: u32,
+}
+
+#[wasm_bindgen]
+impl TickStats {
+    // the number of tiles the board is split into; on the sparse backend
+    // the tiles in use and the empty ones next to a change, on HashLife
+    // none
+    pub fn tiles(&self) -> u32 {
+        self.tiles
+    }
+    // the number of tiles stepped in the last tick
+    pub fn evaluated(&self) -> u32 {
+        self.evaluated
+    }
+    // the number of tiles left as they were
+    pub fn skipped(&self) -> u32 {
+        self.tiles - self.evaluated
+    }
+}
+
+impl TickStats {
+    pub fn new(tiles: u32, evaluated: u32) -> TickStats {
+        TickStats { tiles, evaluated }
+    }
+
+    // every tile of a width x height grid was stepped
+    pub fn whole(width: u32, height: u32) -> TickStats {
+        let tiles = width.div_ceil(TILE) * height.div_ceil(TILE);
+        TickStats::new(tiles, tiles)
+    }
+}
+
+// The 32x32 tiles of the dense grid which changed in the last tick (or
+// were edited since). A cell whose neighbourhood is the same as a
+// generation ago goes to the same state it went to then, which is the
+// state it is in now, so only the tiles within the rule's range of a
+// changed one need stepping.
+pub(crate) struct ActiveTiles {
+    width: u32,
+    height: u32,
+    rows: u32,
+    cols: u32,
+    changed: Vec<bool>,
+}
+
+impl ActiveTiles {
+    // every tile starts out changed
+    pub fn new(width: u32, height: u32) -> ActiveTiles {
+        let (rows, cols) = (height.div_ceil(TILE), width.div_ceil(TILE));
+        ActiveTiles {
+            width,
+            height,
+            rows,
+            cols,
+            changed: vec![true; (rows * cols) as usize],
+        }
+    }
+
+    // step every tile next time, the cells changed behind our back
+    pub fn mark_all(&mut self, width: u32, height: u32) {
+        *self = ActiveTiles::new(width, height);
+    }
+
+    // a single cell was edited
+    pub fn mark_cell(&mut self, row: u32, col: u32) {
+        let idx = (row / TILE * self.cols + col / TILE) as usize;
+        if let Some(changed) = self.changed.get_mut(idx) {
+            *changed = true;
+        }
+    }
+
+    // The (top, left, bottom, right) cells of the tiles to step: those
+    // within range of a changed one, going around the joined edges of a
+    // torus or cylinder. A twisted edge can join a change near one edge
+    // to any other, so there every tile along the edges is stepped too.
+    pub fn regions(&self, topology: Topology, range: u32) -> Vec<(u32, u32, u32, u32)> {
+        let wrap_rows = matches!(topology, Topology::Torus | Topology::VerticalCylinder);
+        let wrap_cols = matches!(topology, Topology::Torus | Topology::HorizontalCylinder);
+        let twisted = matches!(
+            topology,
+            Topology::KleinBottle { .. } | Topology::CrossSurface | Topology::Sphere
+        );
+        // a short last tile brings the tiles on the far side of it closer
+        let reach = range.div_ceil(TILE) as i64;
+        let row_reach = reach + (wrap_rows && !self.height.is_multiple_of(TILE)) as i64;
+        let col_reach = reach + (wrap_cols && !self.width.is_multiple_of(TILE)) as i64;
+        let (rows, cols) = (self.rows as i64, self.cols as i64);
+        let mut active = vec![false; self.changed.len()];
+        let mut edges = false;
+        for idx in (0..self.changed.len()).filter(|&i| self.changed[i]) {
+            let (row, col) = (idx as i64 / cols, idx as i64 % cols);
+            for r in row - row_reach..=row + row_reach {
+                let r = match r {
+                    r if (0..rows).contains(&r) => r,
+                    r if wrap_rows => r.rem_euclid(rows),
+                    _ => continue,
+                };
+                for c in col - col_reach..=col + col_reach {
+                    let c = match c {
+                        c if (0..cols).contains(&c) => c,
+                        c if wrap_cols => c.rem_euclid(cols),
+                        _ => continue,
+                    };
+                    active[(r * cols + c) as usize] = true;
+                }
+            }
+            edges |= self.near_edge(row as u32, col as u32, range);
+        }
+        if edges && twisted {
+            for (idx, active) in active.iter_mut().enumerate() {
+                let (row, col) = (idx as u32 / self.cols, idx as u32 % self.cols);
+                *active |= self.near_edge(row, col, range);
+            }
+        }
+        (0..active.len())
+            .filter(|&i| active[i])
+            .map(|i| {
+                let (top, left) = (i as u32 / self.cols * TILE, i as u32 % self.cols * TILE);
+                (top, left, (top + TILE).min(self.height), (left + TILE).min(self.width))
+            })
+            .collect()
+    }
+
+    // note which of the stepped tiles changed, the others didn't
+    pub fn record(&mut self, regions: &[(u32, u32, u32, u32)], before: &[Cell], after: &[Cell]) {
+        self.changed.iter_mut().for_each(|changed| *changed = false);
+        for &(top, left, bottom, right) in regions {
+            let changed = (top..bottom).any(|row| {
+                let start = (row * self.width) as usize;
+                before[start + left as usize..start + right as usize]
+                    != after[start + left as usize..start + right as usize]
+            });
+            self.changed[(top / TILE * self.cols + left / TILE) as usize] = changed;
+        }
+    }
+
+    pub fn stats(&self, regions: &[(u32, u32, u32, u32)]) -> TickStats {
+        TickStats::new(self.changed.len() as u32, regions.len() as u32)
+    }
+
+    // does the tile hold a cell within range of an edge of the grid
+    fn near_edge(&self, row: u32, col: u32, range: u32) -> bool {
+        let (top, left) = (row * TILE, col * TILE);
+        let (bottom, right) = ((top + TILE).min(self.height), (left + TILE).min(self.width));
+        top < range
+            || left < range
+            || bottom + range > self.height
+            || right + range > self.width
+    }
+}
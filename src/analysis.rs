@@ -0,0 +1,168 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+use wasm_bindgen::prelude::*;
+
+use crate::{Backend, Cell, PackedGrid, Universe};
+
+// what the board settled into
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PeriodKind {
+    // no cycle turned up within the generations searched
+    Unknown = 0,
+    // every cell died
+    Empty = 1,
+    // nothing changes from one generation to the next
+    StillLife = 2,
+    // the board comes back to the same state in place
+    Oscillator = 3,
+    // the board comes back to the same shape, moved (a spaceship)
+    Spaceship = 4,
+}
+
+// the cycle found by `Universe::detect_period`
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PeriodInfo {
+    kind: PeriodKind,
+    period: u32,
+    start: u64,
+    dx: i32,
+    dy: i32,
+}
+
+#[wasm_bindgen]
+impl PeriodInfo {
+    pub fn kind(&self) -> PeriodKind {
+        self.kind
+    }
+    // the number of generations in a cycle
+    pub fn period(&self) -> u32 {
+        self.period
+    }
+    // the generation the cycle began at
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+    // how far a spaceship moves right in one period
+    pub fn dx(&self) -> i32 {
+        self.dx
+    }
+    // how far a spaceship moves down in one period
+    pub fn dy(&self) -> i32 {
+        self.dy
+    }
+}
+
+#[wasm_bindgen]
+impl Universe {
+    // Run a copy of the universe for up to max_gens generations looking
+    // for a cycle. Every generation is hashed as it is and as a shape cut
+    // to its bounding box, a repeated hash is the start of the cycle and
+    // a repeated shape that moved is a spaceship. The universe itself is
+    // left alone.
+    pub fn detect_period(&self, max_gens: u32) -> PeriodInfo {
+        let mut scratch = self.scratch_copy();
+        // generation (from now) each hash was first seen at
+        let mut states: HashMap<u64, u64> = HashMap::new();
+        // generation and top left corner each shape was first seen at
+        let mut shapes: HashMap<u64, (u64, i64, i64)> = HashMap::new();
+
+        for gen in 0..=max_gens as u64 {
+            let cells = scratch.get_cells();
+            let bounds = bounding_box(cells, scratch.width);
+            let (top, left, bottom, right) = match bounds {
+                Some(bounds) => bounds,
+                None => {
+                    return PeriodInfo {
+                        kind: PeriodKind::Empty,
+                        period: 1,
+                        start: self.generation + gen,
+                        dx: 0,
+                        dy: 0,
+                    }
+                }
+            };
+
+            let mut hasher = DefaultHasher::new();
+            cells.hash(&mut hasher);
+            let state = hasher.finish();
+            if let Some(&first) = states.get(&state) {
+                let period = (gen - first) as u32;
+                return PeriodInfo {
+                    kind: if period == 1 {
+                        PeriodKind::StillLife
+                    } else {
+                        PeriodKind::Oscillator
+                    },
+                    period,
+                    start: self.generation + first,
+                    dx: 0,
+                    dy: 0,
+                };
+            }
+            states.insert(state, gen);
+
+            let mut hasher = DefaultHasher::new();
+            (bottom - top, right - left).hash(&mut hasher);
+            for row in top..bottom {
+                let start = (row * scratch.width as i64) as usize;
+                cells[start + left as usize..start + right as usize].hash(&mut hasher);
+            }
+            let shape = hasher.finish();
+            if let Some(&(first, first_top, first_left)) = shapes.get(&shape) {
+                return PeriodInfo {
+                    kind: PeriodKind::Spaceship,
+                    period: (gen - first) as u32,
+                    start: self.generation + first,
+                    dx: (left - first_left) as i32,
+                    dy: (top - first_top) as i32,
+                };
+            }
+            shapes.insert(shape, (gen, top, left));
+
+            scratch.tick();
+        }
+        PeriodInfo {
+            kind: PeriodKind::Unknown,
+            period: 0,
+            start: 0,
+            dx: 0,
+            dy: 0,
+        }
+    }
+}
+
+impl Universe {
+    // a copy of the cells, rule and topology to experiment on, with
+    // no history and on the fastest grid backend for the rule
+    pub(crate) fn scratch_copy(&self) -> Universe {
+        let mut scratch = Universe::blank(self.width, self.height);
+        scratch.cells = self.cells.clone();
+        scratch.rule = self.rule.clone();
+        scratch.topology = self.topology;
+        scratch.generation = self.generation;
+        if PackedGrid::supports(&self.rule, self.topology) {
+            // the packed backend takes any rule the universe holds
+            let _ = scratch.set_backend(Backend::Packed);
+        }
+        scratch
+    }
+}
+
+// the (top, left, bottom, right) box around the live and dying cells,
+// exclusive at the bottom and right
+pub(crate) fn bounding_box(cells: &[Cell], width: u32) -> Option<(i64, i64, i64, i64)> {
+    let width = width.max(1) as usize;
+    let mut bounds: Option<(i64, i64, i64, i64)> = None;
+    for (idx, _) in cells.iter().enumerate().filter(|(_, &c)| c != Cell::DEAD) {
+        let (row, col) = ((idx / width) as i64, (idx % width) as i64);
+        bounds = Some(match bounds {
+            None => (row, col, row + 1, col + 1),
+            Some((t, l, b, r)) => (t.min(row), l.min(col), b.max(row + 1), r.max(col + 1)),
+        });
+    }
+    bounds
+}
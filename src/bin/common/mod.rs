@@ -0,0 +1,173 @@
+// The pattern loading and options shared by the command line binaries
+use std::fs;
+use std::io::{self, Read};
+
+use wasm_game_of_life::formats::{self, Pattern};
+use wasm_game_of_life::{Backend, Topology, Universe};
+
+pub const SETUP_USAGE: &str = "  -r, --rule RULE      rule to run with, ie. B36/S23 or B3/S23:P100,100
+  -t, --topology TOPO  Golly bounded grid, ie. T or K64,64*
+  -s, --size WxH       size of the grid, by default the bounded grid's or
+                       else the pattern's
+  -b, --backend NAME   dense, hashlife, packed or sparse (default dense)
+
+Patterns are read by their extension: .rle, .cells, .lif/.life (1.05 or
+1.06, from the header) and .mc; anything else is tried as RLE.";
+
+// the grid to load the pattern into, as asked for on the command line
+pub struct Setup {
+    pub path: Option<String>,
+    pub rule: Option<String>,
+    pub topology: Option<String>,
+    pub size: Option<(u32, u32)>,
+    pub backend: Backend,
+}
+
+impl Setup {
+    pub fn new() -> Setup {
+        Setup {
+            path: None,
+            rule: None,
+            topology: None,
+            size: None,
+            backend: Backend::Dense,
+        }
+    }
+
+    // take the argument if it is one of the setup's options or the
+    // pattern, returning whether it was
+    pub fn take(
+        &mut self,
+        arg: &str,
+        args: &mut impl Iterator<Item = String>,
+    ) -> Result<bool, String> {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg {
+            "-r" | "--rule" => self.rule = Some(value()?),
+            "-t" | "--topology" => self.topology = Some(value()?),
+            "-s" | "--size" => self.size = Some(parse_size(&value()?)?),
+            "-b" | "--backend" => self.backend = parse_backend(&value()?)?,
+            _ if arg.starts_with('-') && arg != "-" => return Ok(false),
+            _ if self.path.is_none() => self.path = Some(arg.to_string()),
+            _ => return Err("only one pattern can be run at a time".to_string()),
+        }
+        Ok(true)
+    }
+
+    // Build the universe the pattern, rule, topology and size ask for.
+    // Without a pattern the grid starts out empty, 64x64 unless sized.
+    pub fn load(&self) -> Result<Universe, String> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return self.load_pattern(&Pattern::default(), (64, 64)),
+        };
+        let text = read(path)?;
+        let lower = path.to_ascii_lowercase();
+        // macrocells stay in their quadtree, the grid is a window onto them
+        if lower.ends_with(".mc") {
+            let mut universe = Universe::from_macrocell(&text)?;
+            if let Some(rule) = &self.rule {
+                universe.set_rule(rule)?;
+            }
+            if self.backend != Backend::HashLife {
+                universe.set_backend(self.backend)?;
+            }
+            return Ok(universe);
+        }
+        let pattern = parse(&lower, &text)?;
+        self.load_pattern(&pattern, (pattern.width.max(1), pattern.height.max(1)))
+    }
+
+    // the pattern centered in a grid, by default of the given size
+    fn load_pattern(&self, pattern: &Pattern, size: (u32, u32)) -> Result<Universe, String> {
+        // the rule and bounded grid of the pattern unless overridden
+        let (pattern_rule, pattern_grid) = match pattern.rule.as_deref() {
+            Some(rule) => match rule.split_once(':') {
+                Some((rule, grid)) => (Some(rule), Some(grid)),
+                None => (Some(rule), None),
+            },
+            None => (None, None),
+        };
+        let (rule, mut grid) = match self.rule.as_deref() {
+            Some(rule) => match rule.split_once(':') {
+                Some((rule, grid)) => (Some(rule), Some(grid)),
+                None => (Some(rule), pattern_grid),
+            },
+            None => (pattern_rule, pattern_grid),
+        };
+        if let Some(topology) = &self.topology {
+            grid = Some(topology);
+        }
+        let bounded = match grid {
+            Some(grid) => Topology::parse_grid(grid)?.1,
+            None => None,
+        };
+        let (width, height) = self.size.or(bounded).unwrap_or(size);
+        if width < pattern.width || height < pattern.height {
+            return Err(format!(
+                "a {}x{} pattern doesn't fit in a {}x{} grid",
+                pattern.width, pattern.height, width, height
+            ));
+        }
+        let mut universe = Universe::new();
+        universe.set_width(width);
+        universe.set_height(height);
+        if let Some(rule) = rule {
+            universe.set_rule(rule)?;
+        }
+        if let Some(grid) = grid {
+            universe.set_topology(grid)?;
+        }
+        // centered in the grid, as Golly does
+        let (row, col) = ((height - pattern.height) / 2, (width - pattern.width) / 2);
+        universe.load_pattern_at(row, col, pattern)?;
+        universe.set_backend(self.backend)?;
+        Ok(universe)
+    }
+}
+
+// read a pattern in the format its extension names
+fn parse(path: &str, text: &str) -> Result<Pattern, String> {
+    if path.ends_with(".cells") {
+        formats::plaintext::parse(text)
+    } else if path.ends_with(".lif") || path.ends_with(".life") {
+        if text.trim_start().starts_with("#Life 1.05") {
+            formats::life::parse_105(text)
+        } else {
+            formats::life::parse_106(text)
+        }
+    } else {
+        formats::rle::parse(text)
+    }
+}
+
+// the text of a file, or of stdin for -
+fn read(path: &str) -> Result<String, String> {
+    let mut text = String::new();
+    let read = if path == "-" {
+        io::stdin().read_to_string(&mut text).map(|_| ())
+    } else {
+        fs::read_to_string(path).map(|contents| text = contents)
+    };
+    read.map_err(|error| format!("can't read {}: {}", path, error))?;
+    Ok(text)
+}
+
+fn parse_backend(name: &str) -> Result<Backend, String> {
+    match name.to_ascii_lowercase().as_str() {
+        "dense" => Ok(Backend::Dense),
+        "hashlife" => Ok(Backend::HashLife),
+        "packed" => Ok(Backend::Packed),
+        "sparse" => Ok(Backend::Sparse),
+        _ => Err(format!("unknown backend {}", name)),
+    }
+}
+
+// a grid size such as 64x48
+fn parse_size(size: &str) -> Result<(u32, u32), String> {
+    let error = || format!("bad size {}, expected WxH", size);
+    let (width, height) = size.split_once(['x', 'X']).ok_or_else(error)?;
+    let width = width.parse().map_err(|_| error())?;
+    let height = height.parse().map_err(|_| error())?;
+    Ok((width, height))
+}
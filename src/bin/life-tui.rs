@@ -0,0 +1,438 @@
+// An interactive viewer for the terminal, for looking at patterns over
+// ssh without a browser:
+//
+//     cargo run --release --features tui --bin life-tui -- glider.rle --size 256x256
+//
+// Two cells are drawn to a character with the upper half block, in the
+// colours of the rule's palette. The terminal is driven with crossterm,
+// which takes care of raw mode, keys and resizes on unix and windows.
+use std::env;
+use std::io::{self, Write};
+use std::process;
+use std::time::{Duration, Instant};
+
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+
+mod common;
+
+use common::{Setup, SETUP_USAGE};
+use wasm_game_of_life::{Backend, Universe};
+
+const USAGE: &str = "\
+usage: life-tui [options] [pattern file]
+
+keys:
+  space          play or pause
+  n or .         step a generation
+  + and -        double or halve the speed
+  arrows, hjkl   move the cursor
+  t or enter     toggle the cell under the cursor
+  HJKL, shift+arrows  pan
+  z and x        zoom in and out
+  c              center on the cursor
+  q              quit
+
+options:
+  -h, --help           show this
+";
+
+// the keys in short for the status line
+const KEYS: &str = "space play  n step  +/- speed  hjkl move  t toggle  HJKL pan  \
+                    z/x zoom  c center  q quit";
+
+// redraw at most this often when playing fast, running more generations
+// a frame instead
+const FPS: u32 = 32;
+// the fastest speed is 2^MAX_SPEED generations a second
+const MAX_SPEED: u32 = 16;
+// how far in and out the view zooms, see `Viewer::zoom`
+const ZOOM: std::ops::RangeInclusive<i32> = -4..=3;
+
+// colours for what isn't a cell of the grid
+const OUTSIDE: (u8, u8, u8) = (64, 64, 64);
+const CURSOR: (u8, u8, u8) = (255, 64, 64);
+const CURSOR_ALIVE: (u8, u8, u8) = (160, 0, 0);
+
+// a key press the viewer acts on
+enum Key {
+    Char(char),
+    // an arrow's (row, col) direction and whether shift was held
+    Arrow(i64, i64, bool),
+}
+
+struct Viewer {
+    universe: Universe,
+    // the grid cell at the top left of the screen, off the grid when
+    // panned past its edge
+    top: i64,
+    left: i64,
+    // the grid cell the cursor is on
+    cursor: (u32, u32),
+    // each cell is 2^zoom pixels across when zoom is positive, each
+    // pixel 2^-zoom cells across when it is negative; a pixel is half a
+    // character
+    zoom: i32,
+    playing: bool,
+    // 2^speed generations a second
+    speed: u32,
+    // the terminal's rows and cols
+    rows: u32,
+    cols: u32,
+    show_keys: bool,
+}
+
+fn main() {
+    let mut setup = Setup::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let taken = match setup.take(&arg, &mut args) {
+            Ok(taken) => taken,
+            Err(error) => usage_error(&error),
+        };
+        match arg.as_str() {
+            _ if taken => {}
+            "-h" | "--help" => {
+                println!("{}{}", USAGE, SETUP_USAGE);
+                return;
+            }
+            _ => usage_error(&format!("unknown option {}", arg)),
+        }
+    }
+    // stdin is where the keys come from
+    if setup.path.as_deref() == Some("-") {
+        usage_error("the pattern can't come from stdin");
+    }
+    if let Err(error) = run(&setup) {
+        eprintln!("life-tui: {}", error);
+        process::exit(1);
+    }
+}
+
+fn usage_error(error: &str) -> ! {
+    eprintln!("life-tui: {}\n\n{}{}", error, USAGE, SETUP_USAGE);
+    process::exit(2);
+}
+
+fn run(setup: &Setup) -> Result<(), String> {
+    let universe = setup.load()?;
+    let (cols, rows) = terminal::size().map_err(|error| error.to_string())?;
+    let mut viewer = Viewer::new(universe, rows as u32, cols as u32);
+    let _raw = RawMode::enter().map_err(|error| error.to_string())?;
+    let mut stdout = io::stdout();
+    let mut frame = Vec::new();
+    let mut next_frame = Instant::now();
+    loop {
+        frame.clear();
+        viewer.draw(&mut frame).map_err(|error| error.to_string())?;
+        stdout
+            .write_all(&frame)
+            .and_then(|_| stdout.flush())
+            .map_err(|error| error.to_string())?;
+        // wait for the next frame while playing, for as long as it takes
+        // otherwise, then take every event that has come in
+        let mut wait = if viewer.playing {
+            Some(next_frame.saturating_duration_since(Instant::now()))
+        } else {
+            None
+        };
+        loop {
+            let ready = match wait {
+                Some(wait) => event::poll(wait),
+                None => Ok(true),
+            };
+            if !ready.map_err(|error| error.to_string())? {
+                break;
+            }
+            let keep_going = match event::read().map_err(|error| error.to_string())? {
+                Event::Key(key) => key_of(key).is_none_or(|key| viewer.press(key)),
+                Event::Resize(cols, rows) => {
+                    viewer.rows = rows as u32;
+                    viewer.cols = cols as u32;
+                    true
+                }
+                _ => true,
+            };
+            if !keep_going {
+                return Ok(());
+            }
+            wait = Some(Duration::ZERO);
+        }
+        let now = Instant::now();
+        if viewer.playing && now >= next_frame {
+            viewer.advance();
+            next_frame += viewer.frame_time();
+            // don't try to catch up on frames that took too long
+            if next_frame < now {
+                next_frame = now + viewer.frame_time();
+            }
+        }
+    }
+}
+
+impl Viewer {
+    // the grid centered on the screen with the cursor in the middle
+    fn new(universe: Universe, rows: u32, cols: u32) -> Viewer {
+        let cursor = (universe.height() / 2, universe.width() / 2);
+        let mut viewer = Viewer {
+            universe,
+            top: 0,
+            left: 0,
+            cursor,
+            zoom: 0,
+            playing: false,
+            speed: 3,
+            rows,
+            cols,
+            show_keys: false,
+        };
+        viewer.center();
+        viewer
+    }
+
+    // act on a key, returning false to quit
+    fn press(&mut self, key: Key) -> bool {
+        let (rows, cols) = self.view_size();
+        match key {
+            Key::Char('q') | Key::Char('\x03') => return false,
+            Key::Char(' ') => self.playing = !self.playing,
+            Key::Char('n') | Key::Char('.') => {
+                self.playing = false;
+                self.universe.tick();
+            }
+            Key::Char('+') | Key::Char('=') => self.speed = (self.speed + 1).min(MAX_SPEED),
+            Key::Char('-') => self.speed = self.speed.saturating_sub(1),
+            Key::Char('t') | Key::Char('\r') => {
+                self.universe.toggle_cell(self.cursor.0, self.cursor.1)
+            }
+            Key::Char('z') => self.zoom_to(self.zoom + 1),
+            Key::Char('x') => self.zoom_to(self.zoom - 1),
+            Key::Char('c') => self.center(),
+            Key::Char('?') => self.show_keys = !self.show_keys,
+            Key::Char('k') => self.move_cursor(-1, 0),
+            Key::Char('j') => self.move_cursor(1, 0),
+            Key::Char('h') => self.move_cursor(0, -1),
+            Key::Char('l') => self.move_cursor(0, 1),
+            Key::Char('K') => self.top -= rows / 4,
+            Key::Char('J') => self.top += rows / 4,
+            Key::Char('H') => self.left -= cols / 4,
+            Key::Char('L') => self.left += cols / 4,
+            Key::Arrow(dr, dc, false) => self.move_cursor(dr, dc),
+            Key::Arrow(dr, dc, true) => {
+                self.top += dr * rows / 4;
+                self.left += dc * cols / 4;
+            }
+            Key::Char(_) => {}
+        }
+        true
+    }
+
+    // run a frame's worth of generations, a power of two so HashLife
+    // can take them in one step
+    fn advance(&mut self) {
+        let per_frame = FPS.trailing_zeros();
+        if self.speed > per_frame {
+            // MAX_SPEED keeps this well inside what every backend steps
+            self.universe.step(self.speed - per_frame).expect("step within MAX_SPEED");
+        } else {
+            self.universe.tick();
+        }
+    }
+
+    // the time between frames while playing
+    fn frame_time(&self) -> Duration {
+        let rate = (1u32 << self.speed).min(FPS);
+        Duration::from_secs(1) / rate
+    }
+
+    fn move_cursor(&mut self, dr: i64, dc: i64) {
+        let (height, width) = (self.universe.height() as i64, self.universe.width() as i64);
+        let row = (self.cursor.0 as i64 + dr).clamp(0, (height - 1).max(0));
+        let col = (self.cursor.1 as i64 + dc).clamp(0, (width - 1).max(0));
+        self.cursor = (row as u32, col as u32);
+        // keep the cursor on the screen
+        let (rows, cols) = self.view_size();
+        if row < self.top {
+            self.top = row;
+        } else if row >= self.top + rows {
+            self.top = row - rows + 1;
+        }
+        if col < self.left {
+            self.left = col;
+        } else if col >= self.left + cols {
+            self.left = col - cols + 1;
+        }
+    }
+
+    // zoom keeping the cell in the middle of the screen where it is
+    fn zoom_to(&mut self, zoom: i32) {
+        let (rows, cols) = self.view_size();
+        let middle = (self.top + rows / 2, self.left + cols / 2);
+        self.zoom = zoom.clamp(*ZOOM.start(), *ZOOM.end());
+        let (rows, cols) = self.view_size();
+        self.top = middle.0 - rows / 2;
+        self.left = middle.1 - cols / 2;
+    }
+
+    fn center(&mut self) {
+        let (rows, cols) = self.view_size();
+        self.top = self.cursor.0 as i64 - rows / 2;
+        self.left = self.cursor.1 as i64 - cols / 2;
+    }
+
+    // the pixels on the screen, the last row being the status line
+    fn pixels(&self) -> (i64, i64) {
+        (2 * (self.rows.max(2) as i64 - 1), self.cols.max(1) as i64)
+    }
+
+    // the rows and cols of cells on the screen
+    fn view_size(&self) -> (i64, i64) {
+        let (rows, cols) = self.pixels();
+        if self.zoom >= 0 {
+            let size = 1 << self.zoom;
+            ((rows + size - 1) / size, (cols + size - 1) / size)
+        } else {
+            (rows << -self.zoom, cols << -self.zoom)
+        }
+    }
+
+    // queue the whole screen up to be written out in one go
+    fn draw(&self, out: &mut impl Write) -> io::Result<()> {
+        let (pixel_rows, pixel_cols) = self.pixels();
+        let (rows, cols) = self.view_size();
+        let palette = self.universe.palette();
+        let color = |state: u8| {
+            let i = state as usize * 3;
+            match palette.get(i..i + 3) {
+                Some(rgb) => (rgb[0], rgb[1], rgb[2]),
+                None => OUTSIDE,
+            }
+        };
+        // the cells on the screen, measured on the plane of the unbounded
+        // backends which the grid is a window onto
+        let unbounded = matches!(self.universe.backend(), Backend::HashLife | Backend::Sparse);
+        let (plane_row, plane_col) = if unbounded {
+            (self.universe.viewport_row() as i64, self.universe.viewport_col() as i64)
+        } else {
+            (0, 0)
+        };
+        let cells = self.universe.viewport_cells(
+            (self.top + plane_row) as i32,
+            (self.left + plane_col) as i32,
+            cols as u32,
+            rows as u32,
+        );
+        let (height, width) = (self.universe.height() as i64, self.universe.width() as i64);
+        let pixel = |py: i64, px: i64| {
+            // the block of cells under the pixel, a live one showing over
+            // the dying ones and those over the dead
+            let (r, c, size) = if self.zoom >= 0 {
+                (py >> self.zoom, px >> self.zoom, 1)
+            } else {
+                (py << -self.zoom, px << -self.zoom, 1 << -self.zoom)
+            };
+            let (row, col) = (self.top + r, self.left + c);
+            let inside = row < height && row + size > 0 && col < width && col + size > 0;
+            if !unbounded && !inside {
+                return OUTSIDE;
+            }
+            let mut state = 0;
+            for dr in r..(r + size).min(rows) {
+                for dc in c..(c + size).min(cols) {
+                    let cell = cells[(dr * cols + dc) as usize];
+                    if cell == 1 || state != 1 && cell > state {
+                        state = cell;
+                    }
+                }
+            }
+            let (cursor_row, cursor_col) = (self.cursor.0 as i64, self.cursor.1 as i64);
+            if (row..row + size).contains(&cursor_row) && (col..col + size).contains(&cursor_col) {
+                return if state == 1 { CURSOR_ALIVE } else { CURSOR };
+            }
+            color(state)
+        };
+        let rgb = |(r, g, b): (u8, u8, u8)| Color::Rgb { r, g, b };
+        for y in 0..pixel_rows / 2 {
+            queue!(out, MoveTo(0, y as u16))?;
+            let mut last = None;
+            for x in 0..pixel_cols {
+                let colors = (pixel(2 * y, x), pixel(2 * y + 1, x));
+                if last != Some(colors) {
+                    let (upper, lower) = colors;
+                    queue!(out, SetForegroundColor(rgb(upper)), SetBackgroundColor(rgb(lower)))?;
+                    last = Some(colors);
+                }
+                queue!(out, Print('▀'))?;
+            }
+        }
+        let status = if self.show_keys { KEYS.to_string() } else { self.status() };
+        let status: String = status.chars().take(self.cols as usize).collect();
+        let last_row = self.rows.max(2) as u16 - 1;
+        queue!(out, MoveTo(0, last_row), ResetColor, Clear(ClearType::CurrentLine), Print(status))
+    }
+
+    fn status(&self) -> String {
+        let zoom = if self.zoom >= 0 {
+            format!("{}:1", 1 << self.zoom)
+        } else {
+            format!("1:{}", 1 << -self.zoom)
+        };
+        format!(
+            "{} gen {}  pop {}  {} gen/s  zoom {}  cursor {},{}  {}  ? keys",
+            if self.playing { "▶" } else { "‖" },
+            self.universe.generation(),
+            self.universe.population(),
+            1u32 << self.speed,
+            zoom,
+            self.cursor.0,
+            self.cursor.1,
+            self.universe.rule(),
+        )
+    }
+}
+
+// Raw mode for as long as this is held: keys come in one at a time
+// without echo, on the alternate screen with the cursor hidden. The
+// terminal is put back however the viewer ends.
+struct RawMode;
+
+impl RawMode {
+    fn enter() -> io::Result<RawMode> {
+        terminal::enable_raw_mode()?;
+        let raw = RawMode;
+        execute!(io::stdout(), EnterAlternateScreen, Hide, Clear(ClearType::All))?;
+        Ok(raw)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), ResetColor, Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+// the key the viewer knows a key press as, ctrl+c being '\x03' and enter
+// '\r' as they would come from a raw terminal
+fn key_of(event: KeyEvent) -> Option<Key> {
+    // windows reports the keys being let go of as well
+    if event.kind == KeyEventKind::Release {
+        return None;
+    }
+    let shifted = event.modifiers.contains(KeyModifiers::SHIFT);
+    match event.code {
+        KeyCode::Char('c') if event.modifiers.contains(KeyModifiers::CONTROL) => {
+            Some(Key::Char('\x03'))
+        }
+        KeyCode::Char(c) => Some(Key::Char(c)),
+        KeyCode::Enter => Some(Key::Char('\r')),
+        KeyCode::Up => Some(Key::Arrow(-1, 0, shifted)),
+        KeyCode::Down => Some(Key::Arrow(1, 0, shifted)),
+        KeyCode::Left => Some(Key::Arrow(0, -1, shifted)),
+        KeyCode::Right => Some(Key::Arrow(0, 1, shifted)),
+        _ => None,
+    }
+}
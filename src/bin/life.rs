@@ -0,0 +1,215 @@
+// A command line runner for the universe, for batch experiments and
+// regression checks without a browser:
+//
+//     cargo run --release --bin life -- glider.rle --generations 100 --size 64x64
+//
+// The pattern is loaded, stepped and written to stdout; the generation,
+// population and timing go to stderr so the pattern can be piped on.
+// With --record the run is also saved as an animated GIF or APNG:
+//
+//     cargo run --release --bin life -- glider.rle -g 100 --record glider.gif
+use std::env;
+use std::fs;
+use std::process;
+use std::time::Instant;
+
+use wasm_game_of_life::{Recorder, RecordingFormat, Universe};
+
+mod common;
+
+use common::{Setup, SETUP_USAGE};
+
+const USAGE: &str = "\
+usage: life [options] <pattern file, or - for stdin>
+
+options:
+  -g, --generations N  generations to run (default 0)
+  -o, --output FORMAT  rle, cells, life106, life105, mc, display or none
+                       (default rle)
+  --record FILE        save the run as an animation, a GIF for .gif and
+                       an APNG for .png or .apng
+  --delay MS           milliseconds a recorded frame is shown (default 100)
+  --skip N             record only every (N + 1)th generation (default 0)
+  --cell-size N        pixels a recorded cell (default 4)
+  --palette COLORS     comma separated RRGGBB colours of the states from
+                       0 up to record in, the rule's fill in the rest
+  -h, --help           show this
+";
+
+// what was asked for on the command line
+struct Options {
+    setup: Setup,
+    generations: u64,
+    output: String,
+    record: Option<Record>,
+}
+
+// how to record the run, when asked to
+struct Record {
+    path: String,
+    format: RecordingFormat,
+    delay: u32,
+    skip: u32,
+    cell_size: u32,
+    palette: Vec<u8>,
+}
+
+fn main() {
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("life: {}\n\n{}{}", error, USAGE, SETUP_USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(error) = run(&options) {
+        eprintln!("life: {}", error);
+        process::exit(1);
+    }
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    let mut universe = options.setup.load()?;
+    let start = Instant::now();
+    match &options.record {
+        Some(record) => run_recording(&mut universe, options.generations, record)?,
+        None => run_fast(&mut universe, options.generations)?,
+    }
+    let elapsed = start.elapsed();
+    match options.output.as_str() {
+        "rle" => print!("{}", universe.to_rle()),
+        "cells" => print!("{}", universe.to_plaintext()),
+        "life106" => print!("{}", universe.to_life106()),
+        "life105" => print!("{}", universe.to_life105()?),
+        "mc" => print!("{}", universe.to_macrocell()),
+        "display" => print!("{}", universe),
+        _ => {}
+    }
+    let seconds = elapsed.as_secs_f64();
+    let rate = if seconds > 0.0 { options.generations as f64 / seconds } else { 0.0 };
+    eprintln!(
+        "generation {}, population {}, {:.3} ms ({:.1} generations/s)",
+        universe.generation(),
+        universe.population(),
+        seconds * 1000.0,
+        rate
+    );
+    Ok(())
+}
+
+// whole powers of two at a time so HashLife can leap ahead, the other
+// backends tick through them one by one
+fn run_fast(universe: &mut Universe, generations: u64) -> Result<(), String> {
+    let mut exponent = 0;
+    let mut left = generations;
+    while left > 0 {
+        if left & 1 == 1 {
+            universe.step(exponent)?;
+        }
+        left >>= 1;
+        exponent += 1;
+    }
+    Ok(())
+}
+
+// a generation at a time, capturing the start and every one after
+fn run_recording(universe: &mut Universe, generations: u64, record: &Record) -> Result<(), String> {
+    let mut recorder = Recorder::new(record.format, record.cell_size, record.delay);
+    recorder.set_skip(record.skip);
+    recorder.set_palette(&record.palette);
+    recorder.capture(universe)?;
+    for _ in 0..generations {
+        universe.tick();
+        recorder.capture(universe)?;
+    }
+    let animation = recorder.finish()?;
+    fs::write(&record.path, animation).map_err(|e| format!("{}: {}", record.path, e))?;
+    eprintln!("recorded {} frames to {}", recorder.frames(), record.path);
+    Ok(())
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        setup: Setup::new(),
+        generations: 0,
+        output: "rle".to_string(),
+        record: None,
+    };
+    let mut record = Record {
+        path: String::new(),
+        format: RecordingFormat::Gif,
+        delay: 100,
+        skip: 0,
+        cell_size: 4,
+        palette: Vec::new(),
+    };
+    let mut recording = false;
+    while let Some(arg) = args.next() {
+        if options.setup.take(&arg, &mut args)? {
+            continue;
+        }
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "-g" | "--generations" => {
+                let value = value()?;
+                options.generations =
+                    value.parse().map_err(|_| format!("bad generation count {}", value))?;
+            }
+            "-o" | "--output" => {
+                let output = value()?.to_ascii_lowercase();
+                let known = ["rle", "cells", "life106", "life105", "mc", "display", "none"];
+                if !known.contains(&output.as_str()) {
+                    return Err(format!("unknown output format {}", output));
+                }
+                options.output = output;
+            }
+            "--record" => {
+                let path = value()?;
+                let lower = path.to_ascii_lowercase();
+                record.format = if lower.ends_with(".gif") {
+                    RecordingFormat::Gif
+                } else if lower.ends_with(".png") || lower.ends_with(".apng") {
+                    RecordingFormat::Apng
+                } else {
+                    return Err(format!("can't tell what to record {} as", path));
+                };
+                record.path = path;
+                recording = true;
+            }
+            "--delay" => record.delay = parse_number(&value()?, "delay")?,
+            "--skip" => record.skip = parse_number(&value()?, "skip")?,
+            "--cell-size" => record.cell_size = parse_number(&value()?, "cell size")?,
+            "--palette" => record.palette = parse_palette(&value()?)?,
+            "-h" | "--help" => {
+                println!("{}{}", USAGE, SETUP_USAGE);
+                process::exit(0);
+            }
+            _ => return Err(format!("unknown option {}", arg)),
+        }
+    }
+    if options.setup.path.is_none() {
+        return Err("no pattern given".to_string());
+    }
+    if recording {
+        options.record = Some(record);
+    }
+    Ok(options)
+}
+
+fn parse_number(value: &str, what: &str) -> Result<u32, String> {
+    value.parse().map_err(|_| format!("bad {} {}", what, value))
+}
+
+// RRGGBB colours, comma separated, into r, g, b bytes
+fn parse_palette(value: &str) -> Result<Vec<u8>, String> {
+    let mut palette = Vec::new();
+    for color in value.split(',') {
+        let color = color.trim().trim_start_matches('#');
+        let rgb = match u32::from_str_radix(color, 16) {
+            Ok(rgb) if color.len() == 6 => rgb,
+            _ => return Err(format!("bad colour {}", color)),
+        };
+        palette.extend_from_slice(&rgb.to_be_bytes()[1..]);
+    }
+    Ok(palette)
+}
@@ -0,0 +1,354 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use wasm_bindgen::prelude::*;
+
+use crate::{Cell, Rule, Universe};
+
+// the longest period looked for when naming an object
+const MAX_PERIOD: u32 = 1000;
+
+// how far apart the pieces of one object may be, in ranges of the rule
+const MAX_RADIUS: i64 = 4;
+
+// the generations an object is checked against the whole board for
+const CHECK_GENS: usize = 4;
+
+// the digits of the extended Wechsler format
+const DIGITS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+
+// a set of live (and dying) cells as (row, col, state)
+type Shape = Vec<(i64, i64, u8)>;
+
+// one of the rotations or reflections of the plane
+type Transform = fn(i64, i64) -> (i64, i64);
+
+#[wasm_bindgen]
+impl Universe {
+    // The census as text, one "apgcode count" line per kind of object,
+    // the most common first
+    pub fn census_report(&self) -> String {
+        self.census()
+            .iter()
+            .map(|(code, count)| format!("{} {}\n", code, count))
+            .collect()
+    }
+}
+
+impl Universe {
+    // Split the live cells into connected objects and name each of them
+    // by its apgcode: "xs<population>_" for still lifes, "xp<period>_"
+    // for oscillators and "xq<period>_" for spaceships, followed by the
+    // extended Wechsler code of the object in its canonical orientation
+    // (and phase); on a Generations rule the codes of the dying states
+    // follow, one per state. Objects are the 8-connected groups of cells; a group
+    // which doesn't cycle on its own or which runs differently on its own
+    // than on the board (half of an LWSS, a bar of a pulsar) is joined
+    // with the groups around it and tried again, what still doesn't cycle
+    // is counted as "PATHOLOGICAL". Best used once a soup has settled.
+    pub fn census(&self) -> Vec<(String, u32)> {
+        let mut scratch = self.scratch_copy();
+        let mut future = Vec::with_capacity(CHECK_GENS);
+        for _ in 0..CHECK_GENS {
+            scratch.tick();
+            future.push(scratch.get_cells().to_vec());
+        }
+
+        let mut counts: HashMap<String, u32> = HashMap::new();
+        let mut settled = vec![false; self.cells.len()];
+        // Larger than Life objects hold together over their range
+        let range = self.rule.larger().map_or(1, |larger| larger.range() as i64);
+        let max_radius = MAX_RADIUS * range;
+        for radius in 1..=max_radius {
+            for (object, cells) in self.objects(radius, &settled) {
+                let code = apgcode(&object, &self.rule);
+                let alone = code != "PATHOLOGICAL" && self.runs_alone(&object, &future);
+                if !alone && radius < max_radius {
+                    continue;
+                }
+                for idx in cells {
+                    settled[idx] = true;
+                }
+                *counts.entry(code).or_insert(0) += 1;
+            }
+        }
+        let mut census: Vec<(String, u32)> = counts.into_iter().collect();
+        census.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        census
+    }
+
+    // The groups of live and dying cells no more than radius apart,
+    // leaving out the settled ones, along with the flat index of each
+    // cell. Objects reaching over a joined edge are followed around it,
+    // so they come out in one piece.
+    fn objects(&self, radius: i64, settled: &[bool]) -> Vec<(Shape, Vec<usize>)> {
+        let mut seen = settled.to_vec();
+        let mut objects = Vec::new();
+        for start in 0..self.cells.len() {
+            if seen[start] || self.cells[start] == Cell::DEAD {
+                continue;
+            }
+            seen[start] = true;
+            let width = self.width as usize;
+            let (row, col) = ((start / width) as i64, (start % width) as i64);
+            // (grid row, grid col, unwrapped row, unwrapped col)
+            let mut queue = VecDeque::from(vec![(row, col, row, col)]);
+            let mut object = Vec::new();
+            let mut cells = Vec::new();
+            while let Some((row, col, r, c)) = queue.pop_front() {
+                let idx = self.get_index(row as u32, col as u32);
+                object.push((r, c, self.cells[idx].state()));
+                cells.push(idx);
+                for dr in -radius..=radius {
+                    for dc in -radius..=radius {
+                        let next = self.topology.resolve(row + dr, col + dc, self.height, self.width);
+                        if let Some((nr, nc)) = next {
+                            let idx = self.get_index(nr, nc);
+                            if !seen[idx] && self.cells[idx] != Cell::DEAD {
+                                seen[idx] = true;
+                                queue.push_back((nr as i64, nc as i64, r + dr, c + dc));
+                            }
+                        }
+                    }
+                }
+            }
+            objects.push((object, cells));
+        }
+        objects
+    }
+
+    // whether an object runs on its own the way it does on the board, for
+    // the first few generations; the cells next to it must match
+    fn runs_alone(&self, object: &[(i64, i64, u8)], future: &[Vec<Cell>]) -> bool {
+        let mut current = object.to_vec();
+        for cells in future {
+            let next = step(&current, &self.rule);
+            let states: HashMap<(i64, i64), u8> =
+                next.iter().map(|&(r, c, state)| ((r, c), state)).collect();
+            let mut near: HashSet<(i64, i64)> = HashSet::new();
+            for &(r, c, _) in current.iter().chain(next.iter()) {
+                for dr in -1..=1 {
+                    for dc in -1..=1 {
+                        near.insert((r + dr, c + dc));
+                    }
+                }
+            }
+            for &(r, c) in &near {
+                let on_board = match self.topology.resolve(r, c, self.height, self.width) {
+                    Some((row, col)) => cells[self.get_index(row, col)].state(),
+                    None => 0,
+                };
+                if on_board != states.get(&(r, c)).cloned().unwrap_or(0) {
+                    return false;
+                }
+            }
+            current = next;
+        }
+        true
+    }
+}
+
+// move a shape so its bounding box starts at 0, 0 and sort it,
+// returning the old top left corner as well
+fn normalize(shape: &[(i64, i64, u8)]) -> (Shape, (i64, i64)) {
+    let top = shape.iter().map(|&(r, _, _)| r).min().unwrap_or(0);
+    let left = shape.iter().map(|&(_, c, _)| c).min().unwrap_or(0);
+    let mut moved: Shape = shape.iter().map(|&(r, c, s)| (r - top, c - left, s)).collect();
+    moved.sort_unstable();
+    (moved, (top, left))
+}
+
+// step an object on its own, on an unbounded plane
+fn step(shape: &[(i64, i64, u8)], rule: &Rule) -> Shape {
+    let states: HashMap<(i64, i64), u8> = shape.iter().map(|&(r, c, s)| ((r, c), s)).collect();
+    if let Some(larger) = rule.larger() {
+        // a live cell adds its weight to the count of every cell it is
+        // in the neighbourhood of
+        let weights = larger.weights();
+        let mut counts: HashMap<(i64, i64), u32> = HashMap::new();
+        for &(r, c, state) in shape {
+            counts.entry((r, c)).or_insert(0);
+            if state != 1 {
+                continue;
+            }
+            for &(dr, dc, weight) in &weights {
+                *counts.entry((r - dr as i64, c - dc as i64)).or_insert(0) += weight;
+            }
+        }
+        return counts
+            .into_iter()
+            .map(|((r, c), count)| {
+                let cell = Cell::new(states.get(&(r, c)).cloned().unwrap_or(0));
+                (r, c, rule.next_cell_weighted(cell, count).state())
+            })
+            .filter(|&(_, _, state)| state != 0)
+            .collect();
+    }
+    if let Some(table) = rule.table() {
+        // any cell next to one which isn't dead may change
+        let mut near: HashSet<(i64, i64)> = HashSet::new();
+        for &(r, c, _) in shape {
+            near.insert((r, c));
+            for &(dr, dc) in table.offsets() {
+                near.insert((r - dr as i64, c - dc as i64));
+            }
+        }
+        let state = |r: i64, c: i64| states.get(&(r, c)).cloned().unwrap_or(0);
+        return near
+            .into_iter()
+            .map(|(r, c)| {
+                let around: Vec<u8> = table
+                    .offsets()
+                    .iter()
+                    .map(|&(dr, dc)| state(r + dr as i64, c + dc as i64))
+                    .collect();
+                (r, c, table.next(state(r, c), &around))
+            })
+            .filter(|&(_, _, state)| state != 0)
+            .collect();
+    }
+    // the live neighbours of each cell as bits, see `Rule::next_cell_around`
+    let mut around: HashMap<(i64, i64), u8> = HashMap::new();
+    for &(r, c, state) in shape {
+        around.entry((r, c)).or_insert(0);
+        if state != 1 {
+            continue;
+        }
+        // this cell is neighbour `bit` of the cell the offset away the
+        // other way
+        for (bit, &(dr, dc)) in rule.neighborhood().offsets().iter().enumerate() {
+            *around.entry((r - dr as i64, c - dc as i64)).or_insert(0) |= 1 << bit;
+        }
+    }
+    around
+        .into_iter()
+        .map(|((r, c), neighbors)| {
+            let cell = Cell::new(states.get(&(r, c)).cloned().unwrap_or(0));
+            (r, c, rule.next_cell_around(cell, neighbors).state())
+        })
+        .filter(|&(_, _, state)| state != 0)
+        .collect()
+}
+
+// the codes of each state of a normalized shape, the live cells first,
+// joined by _
+fn layers(shape: &[(i64, i64, u8)], states: u8) -> String {
+    let codes: Vec<String> = (1..states).map(|state| wechsler(shape, state)).collect();
+    codes.join("_")
+}
+
+// the extended Wechsler code of the cells of a normalized shape in a
+// state; strips of five rows with a base 32 digit per column, runs of
+// zeros shortened to w, x and y<n>, and the strips joined by z
+fn wechsler(shape: &[(i64, i64, u8)], state: u8) -> String {
+    let height = shape.iter().map(|&(r, _, _)| r + 1).max().unwrap_or(0);
+    let width = shape.iter().map(|&(_, c, _)| c + 1).max().unwrap_or(0);
+    let strips = (height + 4) / 5;
+    let mut columns = vec![vec![0u8; width as usize]; strips as usize];
+    for &(r, c, _) in shape.iter().filter(|&&(_, _, s)| s == state) {
+        columns[(r / 5) as usize][c as usize] |= 1 << (r % 5);
+    }
+    let mut code = String::new();
+    for (i, strip) in columns.iter().enumerate() {
+        if i > 0 {
+            code.push('z');
+        }
+        let end = strip.iter().rposition(|&v| v != 0).map_or(0, |last| last + 1);
+        let mut zeros = 0;
+        for &value in &strip[..end] {
+            if value == 0 {
+                zeros += 1;
+                continue;
+            }
+            push_zeros(&mut code, zeros);
+            zeros = 0;
+            code.push(DIGITS[value as usize] as char);
+        }
+    }
+    code
+}
+
+fn push_zeros(code: &mut String, mut zeros: usize) {
+    while zeros >= 4 {
+        let run = zeros.min(39);
+        code.push('y');
+        code.push(DIGITS[run - 4] as char);
+        zeros -= run;
+    }
+    match zeros {
+        1 => code.push('0'),
+        2 => code.push('w'),
+        3 => code.push('x'),
+        _ => {}
+    }
+}
+
+// the eight rotations and reflections of a shape
+fn orientations(shape: &[(i64, i64, u8)]) -> Vec<Shape> {
+    let transforms: [Transform; 8] = [
+        |r, c| (r, c),
+        |r, c| (c, -r),
+        |r, c| (-r, -c),
+        |r, c| (-c, r),
+        |r, c| (r, -c),
+        |r, c| (-r, c),
+        |r, c| (c, r),
+        |r, c| (-c, -r),
+    ];
+    transforms
+        .iter()
+        .map(|t| {
+            let moved: Shape = shape
+                .iter()
+                .map(|&(r, c, s)| {
+                    let (r, c) = t(r, c);
+                    (r, c, s)
+                })
+                .collect();
+            normalize(&moved).0
+        })
+        .collect()
+}
+
+// pick the shortest code, then the first in ascii order
+fn better(code: &str, best: &Option<String>) -> bool {
+    match best {
+        None => true,
+        Some(best) => (code.len(), code) < (best.len(), best.as_str()),
+    }
+}
+
+// Name an object by running it until it comes back to its first shape
+fn apgcode(object: &[(i64, i64, u8)], rule: &Rule) -> String {
+    let (first, (top, left)) = normalize(object);
+    let mut phases = vec![first.clone()];
+    let mut current = object.to_vec();
+    for period in 1..=MAX_PERIOD {
+        current = step(&current, rule);
+        let (shape, (t, l)) = normalize(&current);
+        if shape == first {
+            let mut best = None;
+            for phase in &phases {
+                for orientation in orientations(phase) {
+                    let code = layers(&orientation, rule.states());
+                    if better(&code, &best) {
+                        best = Some(code);
+                    }
+                }
+            }
+            let best = best.unwrap_or_default();
+            return if (t, l) != (top, left) {
+                format!("xq{}_{}", period, best)
+            } else if period == 1 {
+                let population = first.iter().filter(|&&(_, _, s)| s == 1).count();
+                format!("xs{}_{}", population, best)
+            } else {
+                format!("xp{}_{}", period, best)
+            };
+        }
+        if shape.is_empty() {
+            break;
+        }
+        phases.push(shape);
+    }
+    "PATHOLOGICAL".to_string()
+}
@@ -0,0 +1,215 @@
+// The Life 1.05 and Life 1.06 formats.
+//
+// Life 1.06 is a list of "x y" coordinates of the live cells:
+//
+//   #Life 1.06
+//   0 -1
+//   1 0
+//   -1 1
+//
+// Life 1.05 holds blocks of `.`/`*` rows, each placed by a `#P x y` line
+// relative to the center, with `#D` descriptions and the rule given by
+// `#N` (Conway's life) or `#R` with survival before birth (`#R 23/36`).
+// Only Life-like rules can be written this way:
+//
+//   #Life 1.05
+//   #D Glider
+//   #N
+//   #P -1 -1
+//   .*.
+//   ..*
+//   ***
+//
+// Both place cells at signed coordinates, the patterns are moved so
+// their top left live cell sits at the origin.
+use super::Pattern;
+use crate::{Neighborhood, Rule};
+
+// keep the Life 1.05 rows within the 80 columns the format allows
+const LIFE_105_WIDTH: u32 = 80;
+
+// Turn cells at signed (row, col) coordinates into a pattern
+fn from_coordinates(cells: Vec<(i64, i64)>, pattern: &mut Pattern) -> Result<(), String> {
+    let top = cells.iter().map(|&(row, _)| row).min().unwrap_or(0);
+    let left = cells.iter().map(|&(_, col)| col).min().unwrap_or(0);
+    for (row, col) in cells {
+        let (row, col) = (row - top, col - left);
+        if row > u32::MAX as i64 - 1 || col > u32::MAX as i64 - 1 {
+            return Err("the pattern is too large".to_string());
+        }
+        pattern.width = pattern.width.max(col as u32 + 1);
+        pattern.height = pattern.height.max(row as u32 + 1);
+        pattern.cells.push((row as u32, col as u32, 1));
+    }
+    Ok(())
+}
+
+fn parse_number(value: Option<&str>, line: usize) -> Result<i64, String> {
+    let value = value.ok_or_else(|| format!("Life line {}: missing coordinate", line))?;
+    value
+        .parse()
+        .map_err(|_| format!("Life line {}: bad coordinate '{}'", line, value))
+}
+
+pub fn parse_106(text: &str) -> Result<Pattern, String> {
+    let mut pattern = Pattern::default();
+    let mut cells = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut parts = line.split_whitespace();
+        let x = parse_number(parts.next(), number + 1)?;
+        let y = parse_number(parts.next(), number + 1)?;
+        cells.push((y, x));
+    }
+    from_coordinates(cells, &mut pattern)?;
+    Ok(pattern)
+}
+
+pub fn write_106(pattern: &Pattern) -> String {
+    let mut out = String::from("#Life 1.06\n");
+    for &(row, col, state) in &pattern.cells {
+        if state != 0 {
+            out.push_str(&format!("{} {}\n", col, row));
+        }
+    }
+    out
+}
+
+pub fn parse_105(text: &str) -> Result<Pattern, String> {
+    let mut pattern = Pattern::default();
+    let mut cells = Vec::new();
+    // the block being read; its top left corner and the current row
+    let (mut left, mut row) = (0i64, 0i64);
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim_end();
+        if let Some(header) = line.strip_prefix('#') {
+            let mut chars = header.chars();
+            let kind = chars.next().unwrap_or(' ');
+            let rest = chars.as_str().trim();
+            match kind {
+                'D' | 'C' => pattern.comments.push(rest.to_string()),
+                'N' => pattern.rule = Some("B3/S23".to_string()),
+                'R' => pattern.rule = Some(rest.to_string()),
+                'P' => {
+                    let mut parts = rest.split_whitespace();
+                    left = parse_number(parts.next(), number + 1)?;
+                    row = parse_number(parts.next(), number + 1)?;
+                }
+                // "#Life 1.05" and anything else
+                _ => {}
+            }
+            continue;
+        }
+        for (col, c) in line.chars().enumerate() {
+            match c {
+                '.' => {}
+                '*' | 'O' => cells.push((row, left + col as i64)),
+                c => return Err(format!("Life line {}: unexpected '{}'", number + 1, c)),
+            }
+        }
+        row += 1;
+    }
+    from_coordinates(cells, &mut pattern)?;
+    Ok(pattern)
+}
+
+// the rule as `#R` gives it, survival before birth, for Life-like rules
+fn rule_105(rule: &str) -> Result<String, String> {
+    let unwritable = || format!("Life 1.05 can't hold the rule {}", rule);
+    let parsed: Rule = rule.parse().map_err(|_| unwritable())?;
+    let life_like = parsed.states() == 2
+        && parsed.is_totalistic()
+        && parsed.larger().is_none()
+        && parsed.table().is_none()
+        && parsed.neighborhood() == Neighborhood::Moore;
+    if !life_like {
+        return Err(unwritable());
+    }
+    let counts = |half: &[bool; 9]| -> String {
+        (0..9).filter(|&n| half[n]).map(|n| n.to_string()).collect()
+    };
+    Ok(format!("{}/{}", counts(parsed.survival()), counts(parsed.birth())))
+}
+
+pub fn write_105(pattern: &Pattern) -> Result<String, String> {
+    let mut out = String::from("#Life 1.05\n");
+    for comment in pattern.name.iter().chain(pattern.comments.iter()) {
+        out.push_str(&format!("#D {}\n", comment));
+    }
+    // the format has no room for a bounded grid
+    let rule = pattern.rule.as_deref().map(|rule| rule.split(':').next().unwrap_or(rule));
+    match rule.map(rule_105).transpose()?.as_deref() {
+        None | Some("23/3") => out.push_str("#N\n"),
+        Some(rule) => out.push_str(&format!("#R {}\n", rule)),
+    }
+    // the blocks are placed around the center of the pattern,
+    // wide patterns are cut into bands of columns
+    let grid = pattern.grid();
+    let (top, left) = (-(pattern.height as i64 / 2), -(pattern.width as i64 / 2));
+    let mut band = 0;
+    while band < pattern.width {
+        let band_width = LIFE_105_WIDTH.min(pattern.width - band);
+        let lines: Vec<String> = grid
+            .chunks(pattern.width as usize)
+            .map(|line| {
+                let line = &line[band as usize..(band + band_width) as usize];
+                let text: String = line.iter().map(|&s| if s == 0 { '.' } else { '*' }).collect();
+                text.trim_end_matches('.').to_string()
+            })
+            .collect();
+        if lines.iter().any(|line| !line.is_empty()) {
+            out.push_str(&format!("#P {} {}\n", left + band as i64, top));
+            for line in lines {
+                // an empty row still needs a dot to hold its place
+                out.push_str(if line.is_empty() { "." } else { &line });
+                out.push('\n');
+            }
+        }
+        band += band_width;
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glider(rule: &str) -> Pattern {
+        Pattern {
+            width: 3,
+            height: 3,
+            cells: vec![(0, 1, 1), (1, 2, 1), (2, 0, 1), (2, 1, 1), (2, 2, 1)],
+            rule: Some(rule.to_string()),
+            ..Pattern::default()
+        }
+    }
+
+    #[test]
+    fn writes_rules_survival_first() {
+        let text = write_105(&glider("B36/S23:T64,64")).unwrap();
+        assert!(text.contains("\n#R 23/36\n"), "{}", text);
+        let text = write_105(&glider("B3/S23")).unwrap();
+        assert!(text.contains("\n#N\n"), "{}", text);
+        let text = write_105(&glider("B/S012345678")).unwrap();
+        assert!(text.contains("\n#R 012345678/\n"), "{}", text);
+    }
+
+    #[test]
+    fn reads_back_what_it_writes() {
+        let pattern = glider("B36/S23");
+        let read = parse_105(&write_105(&pattern).unwrap()).unwrap();
+        assert_eq!(read.cells, pattern.cells);
+        let rule: Rule = read.rule.unwrap().parse().unwrap();
+        assert_eq!(rule.to_string(), "B36/S23");
+    }
+
+    #[test]
+    fn refuses_rules_it_cannot_hold() {
+        for rule in ["B2/S/C3", "B2-a/S12", "B2/S34H", "R2,C0,M1,S2..3,B3..3,NM", "WireWorld"] {
+            assert!(write_105(&glider(rule)).is_err(), "{}", rule);
+        }
+    }
+}
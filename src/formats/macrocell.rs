@@ -0,0 +1,264 @@
+// Golly's macrocell format (.mc), a dump of the HashLife quadtree.
+//
+//   [M2] (golly 4.2)
+//   #R B3/S23
+//   $$..*$...*$.***$
+//   4 0 0 0 1
+//
+// Every node line gets a number counting up from 1, 0 being the empty
+// node. Two state patterns write the 8x8 level 3 nodes as bitmaps of
+// `.`/`*` rows each ending in `$`. Multi state patterns write the level 1
+// nodes as "1 nw ne sw se" with the four states instead. The other lines
+// are "level nw ne sw se" with the numbers of the four children, the
+// last node being the root.
+use std::collections::HashMap;
+
+use crate::hashlife::NodeId;
+use crate::{HashLife, Rule};
+
+// Read a macrocell file into a quadtree, the root is centered on the
+// origin the way Golly does. Returns the rule of the file as well.
+pub fn parse(text: &str) -> Result<(HashLife, Option<String>), String> {
+    let mut lines = text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+    match lines.next() {
+        Some((_, line)) if line.starts_with("[M2]") => {}
+        _ => return Err("macrocell files start with '[M2]'".to_string()),
+    }
+
+    let mut rule = None;
+    let mut generation = 0;
+    let mut life: Option<HashLife> = None;
+    let mut ids: Vec<NodeId> = Vec::new();
+
+    for (number, line) in lines {
+        let line = line.trim();
+        let error = |e: String| format!("macrocell line {}: {}", number + 1, e);
+        if let Some(comment) = line.strip_prefix('#') {
+            let mut chars = comment.chars();
+            let kind = chars.next().unwrap_or(' ');
+            let rest = chars.as_str().trim();
+            match kind {
+                'R' => rule = Some(rest.to_string()),
+                'G' => {
+                    generation = rest
+                        .parse()
+                        .map_err(|_| error(format!("bad generation '{}'", rest)))?
+                }
+                _ => {}
+            }
+            continue;
+        }
+        // the rule is known by the first node, the tree is made with it
+        let life = match life.as_mut() {
+            Some(life) => life,
+            None => {
+                let parsed = match &rule {
+                    Some(rule) => rule.split(':').next().unwrap_or("").parse::<Rule>().map_err(error)?,
+                    None => Rule::default(),
+                };
+                if !HashLife::supports(&parsed) {
+                    return Err(error("HashLife can't run B0 or Larger than Life rules".to_string()));
+                }
+                life.get_or_insert(HashLife::new(parsed))
+            }
+        };
+        let id = if line.starts_with(['.', '*', '$']) {
+            read_bitmap(life, line).map_err(error)?
+        } else {
+            read_node(life, line, &ids).map_err(error)?
+        };
+        ids.push(id);
+    }
+
+    let mut life = life.unwrap_or_else(|| HashLife::new(Rule::default()));
+    if let Some(&root) = ids.last() {
+        let half = 1i64 << life.level(root) >> 1;
+        life.set_root(root, -half, -half);
+    }
+    life.set_generation(generation);
+    Ok((life, rule))
+}
+
+// build a node out of a square of states
+fn build(life: &mut HashLife, grid: &[[u8; 8]; 8], level: u8, row: usize, col: usize) -> NodeId {
+    if level == 0 {
+        return life.leaf(grid[row][col]);
+    }
+    let half = 1 << (level - 1);
+    let nw = build(life, grid, level - 1, row, col);
+    let ne = build(life, grid, level - 1, row, col + half);
+    let sw = build(life, grid, level - 1, row + half, col);
+    let se = build(life, grid, level - 1, row + half, col + half);
+    life.join(nw, ne, sw, se)
+}
+
+// an 8x8 leaf written as rows of '.' and '*' ending in '$'
+fn read_bitmap(life: &mut HashLife, line: &str) -> Result<NodeId, String> {
+    let mut grid = [[0u8; 8]; 8];
+    let (mut row, mut col) = (0, 0);
+    for c in line.chars() {
+        match c {
+            '$' => {
+                row += 1;
+                col = 0;
+                continue;
+            }
+            '.' | '*' => {}
+            c => return Err(format!("unexpected '{}' in a leaf", c)),
+        }
+        if row >= 8 || col >= 8 {
+            return Err("leaf is larger than 8x8".to_string());
+        }
+        grid[row][col] = (c == '*') as u8;
+        col += 1;
+    }
+    Ok(build(life, &grid, 3, 0, 0))
+}
+
+// a "level nw ne sw se" node line
+fn read_node(life: &mut HashLife, line: &str, ids: &[NodeId]) -> Result<NodeId, String> {
+    let numbers = line
+        .split_whitespace()
+        .map(|n| n.parse::<usize>().map_err(|_| format!("bad number '{}'", n)))
+        .collect::<Result<Vec<_>, _>>()?;
+    if numbers.len() != 5 {
+        return Err("a node needs a level and four children".to_string());
+    }
+    let level = numbers[0];
+    if level == 0 || level > 62 {
+        return Err(format!("bad level {}", level));
+    }
+    let level = level as u8;
+    let mut children = [0; 4];
+    for (child, &n) in children.iter_mut().zip(numbers[1..].iter()) {
+        *child = if level == 1 {
+            // multi state leaves hold the states of the four cells
+            if n > u8::MAX as usize {
+                return Err(format!("state {} is out of range", n));
+            }
+            life.leaf(n as u8)
+        } else if n == 0 {
+            life.empty(level - 1)
+        } else {
+            let id = *ids
+                .get(n - 1)
+                .ok_or_else(|| format!("node {} is used before it is defined", n))?;
+            if life.level(id) != level - 1 {
+                return Err(format!("node {} has the wrong level", n));
+            }
+            id
+        };
+    }
+    Ok(life.join(children[0], children[1], children[2], children[3]))
+}
+
+// write a quadtree out as a macrocell file
+pub fn write(life: &HashLife, rule: &str) -> String {
+    let mut out = format!("[M2] (wasm-game-of-life)\n#R {}\n", rule);
+    if life.generation() > 0 {
+        out.push_str(&format!("#G {}\n", life.generation()));
+    }
+    let mut writer = Writer {
+        life,
+        two_state: max_state(life, life.root(), &mut HashMap::new()) <= 1,
+        numbers: HashMap::new(),
+        lines: Vec::new(),
+    };
+    writer.emit(life.root());
+    for line in writer.lines {
+        out.push_str(&line);
+        out.push('\n');
+    }
+    out
+}
+
+fn max_state(life: &HashLife, node: NodeId, seen: &mut HashMap<NodeId, u8>) -> u8 {
+    if life.node_population(node) == 0 {
+        return 0;
+    }
+    if life.level(node) == 0 {
+        return life.state(node);
+    }
+    if let Some(&max) = seen.get(&node) {
+        return max;
+    }
+    let max = life
+        .children(node)
+        .iter()
+        .map(|&child| max_state(life, child, seen))
+        .max()
+        .unwrap_or(0);
+    seen.insert(node, max);
+    max
+}
+
+struct Writer<'a> {
+    life: &'a HashLife,
+    two_state: bool,
+    // the line number given to each node written so far
+    numbers: HashMap<NodeId, usize>,
+    lines: Vec<String>,
+}
+
+impl Writer<'_> {
+    // write a node after its children, returning its number
+    fn emit(&mut self, node: NodeId) -> usize {
+        let life = self.life;
+        if life.node_population(node) == 0 {
+            return 0;
+        }
+        if let Some(&number) = self.numbers.get(&node) {
+            return number;
+        }
+        let level = life.level(node);
+        let line = if self.two_state && level == 3 {
+            self.bitmap(node)
+        } else if !self.two_state && level == 1 {
+            let states: Vec<String> = life
+                .children(node)
+                .iter()
+                .map(|&leaf| life.state(leaf).to_string())
+                .collect();
+            format!("1 {}", states.join(" "))
+        } else {
+            let children = life.children(node);
+            let numbers: Vec<String> = children
+                .iter()
+                .map(|&child| self.emit(child).to_string())
+                .collect();
+            format!("{} {}", level, numbers.join(" "))
+        };
+        self.lines.push(line);
+        let number = self.lines.len();
+        self.numbers.insert(node, number);
+        number
+    }
+
+    // the rows of an 8x8 node, leaving off the trailing dead cells
+    fn bitmap(&self, node: NodeId) -> String {
+        let mut grid = [[0u8; 8]; 8];
+        self.read(node, 3, 0, 0, &mut grid);
+        let rows: Vec<String> = grid
+            .iter()
+            .map(|row| {
+                let text: String = row.iter().map(|&s| if s == 0 { '.' } else { '*' }).collect();
+                format!("{}$", text.trim_end_matches('.'))
+            })
+            .collect();
+        let last = grid.iter().rposition(|row| row.iter().any(|&s| s != 0)).unwrap_or(0);
+        rows[..=last].concat()
+    }
+
+    fn read(&self, node: NodeId, level: u8, row: usize, col: usize, grid: &mut [[u8; 8]; 8]) {
+        if level == 0 {
+            grid[row][col] = self.life.state(node);
+            return;
+        }
+        let half = 1 << (level - 1);
+        let [nw, ne, sw, se] = self.life.children(node);
+        self.read(nw, level - 1, row, col, grid);
+        self.read(ne, level - 1, row, col + half, grid);
+        self.read(sw, level - 1, row + half, col, grid);
+        self.read(se, level - 1, row + half, col + half, grid);
+    }
+}
@@ -0,0 +1,39 @@
+// Readers and writers for the pattern file formats shared on LifeWiki
+// and used by Golly. They all go through `Pattern` so the universe only
+// needs to know how to load and save one thing.
+pub mod life;
+pub mod macrocell;
+pub mod plaintext;
+pub mod rle;
+
+// A pattern read from a file, or about to be written to one
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Pattern {
+    // the size of the pattern's bounding box
+    pub width: u32,
+    pub height: u32,
+    // the non dead cells as (row, col, state) measured from the
+    // top left corner of the bounding box
+    pub cells: Vec<(u32, u32, u8)>,
+    // the rulestring, including any bounded grid suffix
+    pub rule: Option<String>,
+    pub name: Option<String>,
+    pub author: Option<String>,
+    pub comments: Vec<String>,
+}
+
+impl Pattern {
+    // the highest state in the pattern
+    pub fn max_state(&self) -> u8 {
+        self.cells.iter().map(|&(_, _, state)| state).max().unwrap_or(0)
+    }
+
+    // the states laid out row by row, dead cells included
+    pub fn grid(&self) -> Vec<u8> {
+        let mut grid = vec![0u8; self.width as usize * self.height as usize];
+        for &(row, col, state) in &self.cells {
+            grid[(row * self.width + col) as usize] = state;
+        }
+        grid
+    }
+}
@@ -0,0 +1,71 @@
+// The plaintext format (.cells) from LifeWiki.
+//
+//   !Name: Glider
+//   !Author: Richard K. Guy
+//   !The smallest spaceship.
+//   .O.
+//   ..O
+//   OOO
+//
+// `.` is a dead cell and `O` a live one, lines may stop short of the
+// width. The ◻/◼ text `Universe::render` writes reads in as well.
+use super::Pattern;
+
+pub fn parse(text: &str) -> Result<Pattern, String> {
+    let mut pattern = Pattern::default();
+    let mut rows: Vec<&str> = Vec::new();
+    for line in text.lines() {
+        let line = line.trim_end();
+        if let Some(comment) = line.strip_prefix('!') {
+            let comment = comment.trim();
+            if let Some(name) = comment.strip_prefix("Name:") {
+                pattern.name = Some(name.trim().to_string());
+            } else if let Some(author) = comment.strip_prefix("Author:") {
+                pattern.author = Some(author.trim().to_string());
+            } else {
+                pattern.comments.push(comment.to_string());
+            }
+            continue;
+        }
+        rows.push(line);
+    }
+    // blank lines at the end are not part of the pattern
+    while rows.last().is_some_and(|row| row.is_empty()) {
+        rows.pop();
+    }
+
+    for (row, line) in rows.iter().enumerate() {
+        let mut width = 0;
+        for (col, c) in line.chars().enumerate() {
+            match c {
+                '.' | '◻' => {}
+                'O' | 'o' | '*' | '◼' => pattern.cells.push((row as u32, col as u32, 1)),
+                c => return Err(format!("plaintext line {}: unexpected '{}'", row + 1, c)),
+            }
+            width = col as u32 + 1;
+        }
+        pattern.width = pattern.width.max(width);
+    }
+    pattern.height = rows.len() as u32;
+    Ok(pattern)
+}
+
+pub fn write(pattern: &Pattern) -> String {
+    let mut out = String::new();
+    if let Some(name) = &pattern.name {
+        out.push_str(&format!("!Name: {}\n", name));
+    }
+    if let Some(author) = &pattern.author {
+        out.push_str(&format!("!Author: {}\n", author));
+    }
+    for comment in &pattern.comments {
+        out.push_str(&format!("!{}\n", comment));
+    }
+    for line in pattern.grid().chunks(pattern.width.max(1) as usize) {
+        for &state in line {
+            out.push(if state == 0 { '.' } else { 'O' });
+        }
+        out.push('\n');
+    }
+    out
+}
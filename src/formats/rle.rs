@@ -0,0 +1,303 @@
+// The run length encoded format, the usual way patterns are shared.
+//
+//   #N Glider
+//   #O Richard K. Guy
+//   x = 3, y = 3, rule = B3/S23
+//   bob$2bo$3o!
+//
+// Two state patterns use `b` for dead and `o` for alive cells, multi
+// state ones use `.` for dead and `A`..`X`, `pA`..`yO` for the states.
+// `$` ends a row and `!` ends the pattern, any of these can carry a run
+// count in front of it.
+use super::Pattern;
+
+// Golly keeps the lines of the encoded pattern under 70 characters
+const LINE_WIDTH: usize = 70;
+
+pub fn parse(text: &str) -> Result<Pattern, String> {
+    let mut pattern = Pattern::default();
+    let mut seen_header = false;
+    let mut reader = Reader::default();
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(comment) = line.strip_prefix('#') {
+            let mut chars = comment.chars();
+            let kind = chars.next().unwrap_or(' ');
+            let text = chars.as_str().trim().to_string();
+            match kind {
+                'N' => pattern.name = Some(text),
+                'O' => pattern.author = Some(text),
+                'C' | 'c' => pattern.comments.push(text),
+                // older files give the rule on a comment line
+                'r' if pattern.rule.is_none() => pattern.rule = Some(text),
+                // positions (#P, #R) and anything else are ignored
+                _ => {}
+            }
+            continue;
+        }
+        if !seen_header && line.starts_with('x') {
+            parse_header(line, &mut pattern).map_err(|e| format!("RLE line {}: {}", number + 1, e))?;
+            seen_header = true;
+            reader.width = pattern.width;
+            reader.height = pattern.height;
+            continue;
+        }
+        if !seen_header {
+            return Err(missing_header());
+        }
+        let done = reader
+            .read_line(line, &mut pattern.cells)
+            .map_err(|e| format!("RLE line {}: {}", number + 1, e))?;
+        if done {
+            break;
+        }
+    }
+    if !seen_header {
+        return Err(missing_header());
+    }
+    Ok(pattern)
+}
+
+fn missing_header() -> String {
+    "RLE is missing the 'x = .., y = ..' header".to_string()
+}
+
+// parse the "x = 3, y = 3, rule = B3/S23" header line
+fn parse_header(line: &str, pattern: &mut Pattern) -> Result<(), String> {
+    // the rule may hold commas itself ("B3/S23:T64,64") so it is
+    // split off before the rest of the header
+    let (sizes, rule) = match line.find("rule") {
+        Some(i) => (&line[..i], Some(&line[i..])),
+        None => (line, None),
+    };
+    for part in sizes.split(',') {
+        let part = part.trim();
+        if part.is_empty() {
+            continue;
+        }
+        let (key, value) = part
+            .split_once('=')
+            .ok_or_else(|| format!("expected 'key = value' but got '{}'", part))?;
+        let value = value.trim();
+        let size = || {
+            value
+                .parse::<u32>()
+                .map_err(|_| format!("bad size '{}' in the header", value))
+        };
+        match key.trim() {
+            "x" => pattern.width = size()?,
+            "y" => pattern.height = size()?,
+            _ => {}
+        }
+    }
+    if let Some(rule) = rule {
+        let (_, value) = rule
+            .split_once('=')
+            .ok_or_else(|| "expected 'rule = ..' in the header".to_string())?;
+        pattern.rule = Some(value.trim().to_string());
+    }
+    Ok(())
+}
+
+// the position of the reader in the encoded cells
+#[derive(Default)]
+struct Reader {
+    row: u32,
+    col: u32,
+    count: Option<u32>,
+    // the size the header gives, the cells have to stay inside it
+    width: u32,
+    height: u32,
+}
+
+impl Reader {
+    // read one line of the encoded cells, returns true at the final '!'
+    fn read_line(&mut self, line: &str, cells: &mut Vec<(u32, u32, u8)>) -> Result<bool, String> {
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            let state = match c {
+                '0'..='9' => {
+                    let digit = c as u32 - '0' as u32;
+                    let count = self.count.unwrap_or(0);
+                    self.count = Some(
+                        count
+                            .checked_mul(10)
+                            .and_then(|count| count.checked_add(digit))
+                            .ok_or_else(|| "run count is too large".to_string())?,
+                    );
+                    continue;
+                }
+                '$' => {
+                    let count = self.count.take().unwrap_or(1);
+                    self.row = self.row.checked_add(count).ok_or_else(too_long)?;
+                    self.col = 0;
+                    continue;
+                }
+                '!' => return Ok(true),
+                c if c.is_whitespace() => continue,
+                'b' | '.' => 0,
+                'A'..='X' => c as u32 - 'A' as u32 + 1,
+                'p'..='y' if chars.peek().is_some_and(|n| ('A'..='X').contains(n)) => {
+                    let letter = chars.next().unwrap();
+                    (c as u32 - 'p' as u32 + 1) * 24 + (letter as u32 - 'A' as u32 + 1)
+                }
+                // two state patterns may use any other letter for alive cells
+                c if c.is_ascii_lowercase() => 1,
+                c => return Err(format!("unexpected '{}'", c)),
+            };
+            if state > u8::MAX as u32 {
+                return Err(format!("state {} is out of range", state));
+            }
+            let run = self.count.take().unwrap_or(1);
+            let end = self.col.checked_add(run).ok_or_else(too_long)?;
+            if state != 0 {
+                if end > self.width || self.row >= self.height {
+                    return Err(format!(
+                        "cells past the {}x{} the header gives",
+                        self.width, self.height
+                    ));
+                }
+                for col in self.col..end {
+                    cells.push((self.row, col, state as u8));
+                }
+            }
+            self.col = end;
+        }
+        Ok(false)
+    }
+}
+
+fn too_long() -> String {
+    "run goes past the largest grid".to_string()
+}
+
+// the token for a run of a state
+fn token(state: u8, run: u32, multi_state: bool) -> String {
+    let symbol = match (state, multi_state) {
+        (0, false) => "b".to_string(),
+        (_, false) => "o".to_string(),
+        (0, true) => ".".to_string(),
+        (s, true) if s <= 24 => ((b'A' + s - 1) as char).to_string(),
+        (s, true) => {
+            let s = s - 1;
+            let prefix = (b'p' + s / 24 - 1) as char;
+            let letter = (b'A' + s % 24) as char;
+            format!("{}{}", prefix, letter)
+        }
+    };
+    if run > 1 {
+        format!("{}{}", run, symbol)
+    } else {
+        symbol
+    }
+}
+
+pub fn write(pattern: &Pattern) -> String {
+    let mut out = String::new();
+    if let Some(name) = &pattern.name {
+        out.push_str(&format!("#N {}\n", name));
+    }
+    if let Some(author) = &pattern.author {
+        out.push_str(&format!("#O {}\n", author));
+    }
+    for comment in &pattern.comments {
+        out.push_str(&format!("#C {}\n", comment));
+    }
+    out.push_str(&format!("x = {}, y = {}", pattern.width, pattern.height));
+    if let Some(rule) = &pattern.rule {
+        out.push_str(&format!(", rule = {}", rule));
+    }
+    out.push('\n');
+
+    // encode the rows into tokens, empty rows fold into the run of '$'
+    let multi_state = pattern.max_state() > 1;
+    let grid = pattern.grid();
+    let mut tokens = Vec::new();
+    let mut pending_rows = 0;
+    for line in grid.chunks(pattern.width.max(1) as usize) {
+        let mut runs: Vec<(u8, u32)> = Vec::new();
+        for &state in line {
+            match runs.last_mut() {
+                Some((last, run)) if *last == state => *run += 1,
+                _ => runs.push((state, 1)),
+            }
+        }
+        // trailing dead cells are left out
+        if let Some(&(0, _)) = runs.last() {
+            runs.pop();
+        }
+        if !runs.is_empty() {
+            if pending_rows > 0 {
+                tokens.push(if pending_rows > 1 {
+                    format!("{}$", pending_rows)
+                } else {
+                    "$".to_string()
+                });
+            }
+            for (state, run) in runs {
+                tokens.push(token(state, run, multi_state));
+            }
+            pending_rows = 0;
+        }
+        pending_rows += 1;
+    }
+    tokens.push("!".to_string());
+
+    // wrap the tokens without splitting any of them
+    let mut line_len = 0;
+    for token in tokens {
+        if line_len + token.len() > LINE_WIDTH {
+            out.push('\n');
+            line_len = 0;
+        }
+        line_len += token.len();
+        out.push_str(&token);
+    }
+    out.push('\n');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Universe;
+
+    #[test]
+    fn reads_runs_and_rows() {
+        let pattern = parse("#N Glider\nx = 3, y = 3, rule = B3/S23\nbob$2bo$3o!").unwrap();
+        assert_eq!(pattern.name.as_deref(), Some("Glider"));
+        assert_eq!((pattern.width, pattern.height), (3, 3));
+        assert_eq!(pattern.cells, vec![(0, 1, 1), (1, 2, 1), (2, 0, 1), (2, 1, 1), (2, 2, 1)]);
+    }
+
+    #[test]
+    fn refuses_cells_past_the_header() {
+        for rle in ["x = 2, y = 2\n3o!", "x = 2, y = 1\no$o!", "x = 3, y = 3\n2$2b2o!"] {
+            assert!(parse(rle).is_err(), "{}", rle);
+        }
+        // trailing dead cells and rows are fine
+        assert!(parse("x = 2, y = 1\n2o3b2$!").is_ok());
+    }
+
+    #[test]
+    fn refuses_runs_too_long_to_count() {
+        for rle in [
+            "x = 1, y = 1\n4294967295b2bo!",
+            "x = 1, y = 1\n4294967295$4294967295$o!",
+            "x = 1, y = 1\n4294967296o!",
+        ] {
+            assert!(parse(rle).is_err(), "{}", rle);
+        }
+    }
+
+    #[test]
+    fn refuses_universes_too_big_to_hold() {
+        assert!(Universe::from_rle("x = 100000, y = 100000\no!").is_err());
+        assert!(Universe::from_rle("x = 1, y = 1, rule = B3/S23:T100000,100000\no!").is_err());
+        assert!(Universe::from_rle("x = 1000, y = 1000\no!").is_ok());
+    }
+}
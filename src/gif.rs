@@ -0,0 +1,268 @@
+// An animated GIF writer for frames of palette indices: a global colour
+// table, a looping extension and each frame whole, LZW compressed.
+
+// the largest code LZW may use, 12 bits
+const MAX_CODE: u32 = 4095;
+
+// Encode width x height frames of indices into the palette (r, g, b
+// bytes, at most 256 colours), each shown for delay hundredths of a
+// second, looping forever.
+pub(crate) fn encode(
+    width: u16,
+    height: u16,
+    palette: &[u8],
+    frames: &[Vec<u8>],
+    delay: u16,
+) -> Vec<u8> {
+    // the colour table has a power of two entries, at least 4 so the
+    // minimum LZW code size stays at 2
+    let colors = (palette.len() / 3).clamp(4, 256);
+    let bits = colors.next_power_of_two().trailing_zeros();
+    let mut gif = b"GIF89a".to_vec();
+    gif.extend_from_slice(&width.to_le_bytes());
+    gif.extend_from_slice(&height.to_le_bytes());
+    // a global colour table of 2^bits entries, 8 bits a channel
+    gif.push(0x80 | 0x70 | (bits - 1) as u8);
+    gif.extend_from_slice(&[0, 0]);
+    let mut table = palette[..palette.len().min(256 * 3)].to_vec();
+    table.resize(3 << bits, 0);
+    gif.extend_from_slice(&table);
+    // the NETSCAPE2.0 application extension, looping forever
+    gif.extend_from_slice(b"\x21\xFF\x0BNETSCAPE2.0\x03\x01\x00\x00\x00");
+    for frame in frames {
+        // a graphic control extension for the delay, no transparency
+        gif.extend_from_slice(&[0x21, 0xF9, 0x04, 0x00]);
+        gif.extend_from_slice(&delay.to_le_bytes());
+        gif.extend_from_slice(&[0x00, 0x00]);
+        // the image covers the whole screen and uses the global table
+        gif.push(0x2C);
+        gif.extend_from_slice(&[0, 0, 0, 0]);
+        gif.extend_from_slice(&width.to_le_bytes());
+        gif.extend_from_slice(&height.to_le_bytes());
+        gif.push(0);
+        gif.push(bits as u8);
+        // the data goes in blocks of at most 255 bytes, ended by an empty one
+        for block in lzw(frame, bits).chunks(255) {
+            gif.push(block.len() as u8);
+            gif.extend_from_slice(block);
+        }
+        gif.push(0);
+    }
+    gif.push(0x3B);
+    gif
+}
+
+// LZW compress indices of size bits each, as GIF does it: codes packed
+// least significant bit first and growing a bit at a time from size + 1
+// bits, starting over with a clear code once all 4096 are used.
+fn lzw(indices: &[u8], size: u32) -> Vec<u8> {
+    let clear = 1u32 << size;
+    let end = clear + 1;
+    let mut bits = Bits::default();
+    // the code of each string plus a following index, 0 for none yet
+    let mut table = vec![0u16; (MAX_CODE as usize + 1) << size];
+    let (mut width, mut next) = (size + 1, end);
+    bits.write(clear, width);
+    let mut indices = indices.iter().map(|&i| (i as u32).min(clear - 1));
+    let mut code = match indices.next() {
+        Some(first) => first,
+        None => {
+            bits.write(end, width);
+            return bits.finish();
+        }
+    };
+    for index in indices {
+        let slot = ((code << size) | index) as usize;
+        if table[slot] != 0 {
+            code = table[slot] as u32;
+            continue;
+        }
+        bits.write(code, width);
+        code = index;
+        // the new string takes the next code, widening the codes when
+        // it no longer fits
+        next += 1;
+        if next == 1 << width {
+            width += 1;
+        }
+        if next == MAX_CODE {
+            bits.write(clear, width);
+            table.iter_mut().for_each(|entry| *entry = 0);
+            width = size + 1;
+            next = end;
+            continue;
+        }
+        table[slot] = next as u16;
+    }
+    bits.write(code, width);
+    next += 1;
+    if next == 1 << width {
+        width += 1;
+    }
+    if next == MAX_CODE {
+        bits.write(clear, width);
+        width = size + 1;
+    }
+    bits.write(end, width);
+    bits.finish()
+}
+
+// writes codes from the least significant bit up
+#[derive(Default)]
+struct Bits {
+    out: Vec<u8>,
+    bits: u64,
+    count: u32,
+}
+
+impl Bits {
+    fn write(&mut self, code: u32, width: u32) {
+        self.bits |= (code as u64) << self.count;
+        self.count += width;
+        while self.count >= 8 {
+            self.out.push(self.bits as u8);
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.bits as u8);
+        }
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Random;
+
+    // what a GIF reader makes of the codes, along with the number of
+    // clear codes and the widest code it read
+    struct Decoded {
+        indices: Vec<u8>,
+        clears: usize,
+        widest: u32,
+    }
+
+    // Undo `lzw` the way GIF readers do: the codes widen once the next
+    // code no longer fits, up to 12 bits, and a clear code starts the
+    // table over
+    fn unlzw(data: &[u8], size: u32) -> Decoded {
+        let (clear, end) = (1u32 << size, (1u32 << size) + 1);
+        let mut decoded = Decoded {
+            indices: Vec::new(),
+            clears: 0,
+            widest: 0,
+        };
+        let mut table: Vec<Vec<u8>> = Vec::new();
+        let mut width = size + 1;
+        let mut previous: Option<Vec<u8>> = None;
+        let mut at = 0;
+        loop {
+            let code = (0..width).fold(0, |code, i| {
+                let bit = data[(at + i as usize) / 8] >> ((at + i as usize) % 8) & 1;
+                code | (bit as u32) << i
+            });
+            at += width as usize;
+            decoded.widest = decoded.widest.max(width);
+            if code == clear {
+                table = (0..clear).map(|i| vec![i as u8]).collect();
+                // the clear and end codes hold places in the table
+                table.push(Vec::new());
+                table.push(Vec::new());
+                width = size + 1;
+                previous = None;
+                decoded.clears += 1;
+                continue;
+            }
+            if code == end {
+                return decoded;
+            }
+            let entry = match (table.get(code as usize), &previous) {
+                (Some(entry), _) => entry.clone(),
+                // the code being made, the previous string and its first index
+                (None, Some(previous)) if code as usize == table.len() => {
+                    let mut entry = previous.clone();
+                    entry.push(previous[0]);
+                    entry
+                }
+                _ => panic!("code {} isn't in the table", code),
+            };
+            if let Some(mut string) = previous.take() {
+                if table.len() <= MAX_CODE as usize {
+                    string.push(entry[0]);
+                    table.push(string);
+                    if table.len() == 1 << width && width < 12 {
+                        width += 1;
+                    }
+                }
+            }
+            decoded.indices.extend_from_slice(&entry);
+            previous = Some(entry);
+        }
+    }
+
+    #[test]
+    fn lzw_decodes_back() {
+        let mut random = Random::new(3);
+        let mut noise = |count: usize, colors: u64| -> Vec<u8> {
+            (0..count).map(|_| (random.next_u64() % colors) as u8).collect()
+        };
+        // (indices, code size)
+        let cases = [
+            (Vec::new(), 2),
+            (vec![1], 2),
+            (vec![0; 10_000], 2),
+            (noise(50_000, 4), 2),
+            (noise(50_000, 256), 8),
+            ((0..=255).cycle().take(20_000).collect(), 8),
+        ];
+        for (indices, size) in &cases {
+            let decoded = unlzw(&lzw(indices, *size), *size);
+            assert!(decoded.indices == *indices, "{} indices of {} bits", indices.len(), size);
+        }
+        // the noise uses every code up, widening to 12 bits and clearing
+        // the table to start over more than once after the first clear
+        let decoded = unlzw(&lzw(&cases[3].0, 2), 2);
+        assert_eq!(decoded.widest, 12);
+        assert!(decoded.clears > 2, "{} clears", decoded.clears);
+        // two indices add a single string, the 3 bit codes still have
+        // room for it, while the four strings of five indices fill them
+        let decoded = unlzw(&lzw(&[0, 1], 2), 2);
+        assert_eq!((decoded.widest, decoded.clears), (3, 1));
+        let decoded = unlzw(&lzw(&[0, 1, 2, 3, 0], 2), 2);
+        assert_eq!((decoded.indices, decoded.widest), (vec![0, 1, 2, 3, 0], 4));
+    }
+
+    #[test]
+    fn frames_decode_from_the_blocks() {
+        let frames = [vec![0, 1, 2, 3, 3, 2, 1, 0], vec![3; 8]];
+        let palette = [0, 0, 0, 255, 0, 0, 0, 255, 0, 0, 0, 255];
+        let gif = encode(4, 2, &palette, &frames, 10);
+        assert_eq!(&gif[..6], b"GIF89a");
+        assert_eq!(gif[6..10], [4, 0, 2, 0]);
+        // a global table of 4 colours follows the screen descriptor
+        assert_eq!(gif[10] & 0x87, 0x81);
+        assert_eq!(gif[13..25], palette);
+        let mut at = 25 + 19;
+        for frame in &frames {
+            assert_eq!(gif[at..at + 8], [0x21, 0xF9, 0x04, 0x00, 10, 0, 0, 0]);
+            assert_eq!(gif[at + 8], 0x2C);
+            at += 18;
+            let size = gif[at] as u32;
+            at += 1;
+            let mut data = Vec::new();
+            while gif[at] != 0 {
+                let length = gif[at] as usize;
+                data.extend_from_slice(&gif[at + 1..at + 1 + length]);
+                at += 1 + length;
+            }
+            at += 1;
+            assert_eq!(&unlzw(&data, size).indices, frame);
+        }
+        assert_eq!(gif[at..], [0x3B]);
+    }
+}
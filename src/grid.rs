@@ -0,0 +1,318 @@
+use crate::{Cell, Rule, Topology};
+
+// A byte per cell grid to step cell by cell; the universe's cells on the
+// dense backend, or a tile of the sparse backend with the cells around it
+pub(crate) struct Grid<'a> {
+    pub cells: &'a [Cell],
+    pub width: u32,
+    pub height: u32,
+    pub topology: Topology,
+}
+
+impl Grid<'_> {
+    // work the cells out for the next generation
+    pub fn step(&self, rule: &Rule) -> Vec<Cell> {
+        self.step_regions(rule, &[(0, 0, self.height, self.width)])
+    }
+
+    // work out the next generation of the cells inside the (top, left,
+    // bottom, right) rectangles, the cells outside are copied as they are
+    pub fn step_regions(&self, rule: &Rule, regions: &[(u32, u32, u32, u32)]) -> Vec<Cell> {
+        // get the flat vect of cells in the universe
+        let mut next = self.cells.to_vec();
+        if regions.is_empty() || self.width == 0 {
+            return next;
+        }
+        // Larger than Life rules count all of their neighbourhoods in one
+        // go, the counts are then put to the rule cell by cell
+        let counts = rule
+            .larger()
+            .map(|larger| larger.counts(self.cells, self.width, self.height, self.topology));
+        let counts = counts.as_deref();
+        // with the simd feature the rules that only count their live
+        // Moore neighbours have them counted region by region as well
+        #[cfg(feature = "simd")]
+        let live = crate::simd::supports(rule).then(|| {
+            let (width, height) = (self.width, self.height);
+            crate::simd::neighbor_counts(self.cells, width, height, self.topology, regions)
+        });
+        #[cfg(not(feature = "simd"))]
+        let live: Option<Vec<u8>> = None;
+        let live = live.as_deref();
+        // Every cell is worked out from the current cells alone, so the
+        // rows can be split into bands stepped side by side on rayon's pool
+        // and the result is the same whichever thread got to which band
+        #[cfg(feature = "parallel")]
+        {
+            use rayon::prelude::*;
+            let rows = band_rows(self.width, self.height);
+            if rows < self.height {
+                next.par_chunks_mut((rows * self.width) as usize)
+                    .enumerate()
+                    .for_each(|(band, cells)| {
+                        self.step_band(rule, regions, counts, live, band as u32 * rows, cells)
+                    });
+                return next;
+            }
+        }
+        self.step_band(rule, regions, counts, live, 0, &mut next);
+        next
+    }
+
+    // step the parts of the regions that fall in the band of rows
+    // starting at the first row, whose cells the band holds
+    fn step_band(
+        &self,
+        rule: &Rule,
+        regions: &[(u32, u32, u32, u32)],
+        counts: Option<&[u32]>,
+        live: Option<&[u8]>,
+        first: u32,
+        band: &mut [Cell],
+    ) {
+        let last = first + band.len() as u32 / self.width;
+        let offset = self.get_index(first, 0);
+        if let Some(counts) = counts {
+            for &(top, left, bottom, right) in regions {
+                for row in top.max(first)..bottom.min(last) {
+                    for col in left..right {
+                        let idx = self.get_index(row, col);
+                        band[idx - offset] = rule.next_cell_weighted(self.cells[idx], counts[idx]);
+                    }
+                }
+            }
+        } else if let Some(live) = live {
+            for &(top, left, bottom, right) in regions {
+                for row in top.max(first)..bottom.min(last) {
+                    for col in left..right {
+                        let idx = self.get_index(row, col);
+                        band[idx - offset] = rule.next_cell(self.cells[idx], live[idx]);
+                    }
+                }
+            }
+        } else if let Some(table) = rule.table() {
+            // rule tables look at the state of every neighbour
+            let mut around = Vec::with_capacity(table.offsets().len());
+            for &(top, left, bottom, right) in regions {
+                for row in top.max(first)..bottom.min(last) {
+                    for col in left..right {
+                        let idx = self.get_index(row, col);
+                        self.neighbor_states(row, col, table.offsets(), &mut around);
+                        let state = table.next(self.cells[idx].state(), &around);
+                        band[idx - offset] = Cell::new(state);
+                    }
+                }
+            }
+        } else {
+            // Iterate over the universe grid, a region at a time
+            for &(top, left, bottom, right) in regions {
+                for row in top.max(first)..bottom.min(last) {
+                    for col in left..right {
+                        // get the current flat index
+                        let idx = self.get_index(row, col);
+                        // get the current cell
+                        let cell = self.cells[idx];
+                        // find the living neighbors
+                        let neighbors_alive = self.live_neighbors(rule, row, col);
+                        // ask the rule for the next cell state given the current
+                        // cell and its living neighbors
+                        let next_cell = rule.next_cell_around(cell, neighbors_alive);
+                        // update the state of the cell for the next tick
+                        band[idx - offset] = next_cell;
+                    }
+                }
+            }
+        }
+    }
+
+    // given the row and column find the
+    // flatten index of the cell
+    fn get_index(&self, row: u32, column: u32) -> usize {
+        // idx = row * width + col
+        (row * self.width + column) as usize
+    }
+    // find the live neighbors around a given cell
+    // located at a given row col index; bit i is set when the
+    // neighbour at the i-th offset of the neighbourhood is alive, so
+    // isotropic rules can tell where they are and not just how many
+    fn live_neighbors(&self, rule: &Rule, row: u32, column: u32) -> u8 {
+        // a mutable to hold the neighbour bits
+        let mut neighbors = 0;
+        // iterate using the deltas of the rule's neighbourhood
+        for (bit, &(delta_row, delta_col)) in rule.neighborhood().offsets().iter().enumerate() {
+            // let the topology handle the univers edges, on a torus
+            // the neighbor of an edge cell will be the edge cell at
+            // the other side of the universe, on a plane it is dead
+            let neighbor = self.topology.resolve(
+                row as i64 + delta_row as i64,
+                column as i64 + delta_col as i64,
+                self.height,
+                self.width,
+            );
+            let (neighbor_row, neighbor_col) = match neighbor {
+                Some(location) => location,
+                None => continue,
+            };
+            // get the vector index of the neighbor row and col
+            let idx = self.get_index(neighbor_row, neighbor_col);
+            // set the bit of the alive neighbor cells
+            // if alive: set the bit
+            // if dead or dying: do nothing
+            neighbors |= ((self.cells[idx] == Cell::ALIVE) as u8) << bit;
+        }
+        neighbors
+    }
+    // the states of the neighbours at the offsets around a cell, in
+    // order; past the edge of a plane they are dead
+    fn neighbor_states(&self, row: u32, column: u32, offsets: &[(i32, i32)], states: &mut Vec<u8>) {
+        states.clear();
+        for &(delta_row, delta_col) in offsets {
+            let neighbor = self.topology.resolve(
+                row as i64 + delta_row as i64,
+                column as i64 + delta_col as i64,
+                self.height,
+                self.width,
+            );
+            states.push(match neighbor {
+                Some((r, c)) => self.cells[self.get_index(r, c)].state(),
+                None => 0,
+            });
+        }
+    }
+}
+
+// The rows in each band when stepping on several threads: the grid is
+// shared out between the threads of the pool, but a band is kept big
+// enough to be worth handing to one
+#[cfg(feature = "parallel")]
+fn band_rows(width: u32, height: u32) -> u32 {
+    const MIN_BAND_CELLS: u32 = 16 * 1024;
+    let threads = rayon::current_num_threads() as u32;
+    let bands = (width * height / MIN_BAND_CELLS).clamp(1, threads);
+    height.div_ceil(bands)
+}
+
+#[cfg(all(test, any(feature = "parallel", feature = "simd")))]
+mod tests {
+    use super::*;
+    use crate::Random;
+
+    const TOPOLOGIES: [Topology; 8] = [
+        Topology::Torus,
+        Topology::Plane,
+        Topology::HorizontalCylinder,
+        Topology::VerticalCylinder,
+        Topology::KleinBottle { twisted_rows: true },
+        Topology::KleinBottle { twisted_rows: false },
+        Topology::CrossSurface,
+        Topology::Sphere,
+    ];
+
+    // Life-like, isotropic, Generations, hexagonal, Larger than Life and
+    // rule table rules, each stepped its own way
+    #[cfg(feature = "parallel")]
+    const RULES: [&str; 7] = [
+        "B3/S23",
+        "B36/S23",
+        "B2-a/S12",
+        "B2/S/C3",
+        "B2/S34H",
+        "R2,C0,M1,S5..9,B6..8,NM",
+        "WireWorld",
+    ];
+
+    // a width x height soup of every state of the rule
+    fn soup(rule: &Rule, width: u32, height: u32, seed: u64) -> Vec<Cell> {
+        let mut random = Random::new(seed);
+        let states = rule.states() as u64;
+        (0..width * height)
+            .map(|_| Cell::new((random.next_u64() % states) as u8))
+            .collect()
+    }
+
+    // the simd counts are what counting cell by cell finds, inside the
+    // regions asked for
+    #[cfg(feature = "simd")]
+    #[test]
+    fn simd_counts_like_live_neighbors() {
+        // dying cells are there but don't count
+        let rule: Rule = "B2/S/C3".parse().unwrap();
+        let sizes = [(64, 64), (77, 45), (130, 130), (33, 1), (1, 19), (2, 2)];
+        for (seed, &(width, height)) in sizes.iter().enumerate() {
+            let cells = soup(&rule, width, height, seed as u64);
+            let whole = vec![(0, 0, height, width)];
+            let parts = vec![(height / 3, width / 4, height, width), (0, 0, 1, width / 2 + 1)];
+            for topology in TOPOLOGIES {
+                if topology == Topology::Sphere && width != height {
+                    continue;
+                }
+                let grid = Grid {
+                    cells: &cells,
+                    width,
+                    height,
+                    topology,
+                };
+                for regions in [&whole, &parts] {
+                    let counts =
+                        crate::simd::neighbor_counts(&cells, width, height, topology, regions);
+                    for row in 0..height {
+                        for col in 0..width {
+                            let inside = regions.iter().any(|&(top, left, bottom, right)| {
+                                (top..bottom).contains(&row) && (left..right).contains(&col)
+                            });
+                            let expected = match inside {
+                                true => grid.live_neighbors(&rule, row, col).count_ones() as u8,
+                                false => 0,
+                            };
+                            let idx = grid.get_index(row, col);
+                            assert_eq!(
+                                counts[idx], expected,
+                                "{}x{} {:?} at {}, {}",
+                                width, height, topology, row, col
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // stepping in bands on several threads gives the same cells as on one
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn parallel_steps_like_serial() {
+        let pool = |threads| {
+            rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap()
+        };
+        let (serial, parallel) = (pool(1), pool(4));
+        // square for the sphere and big enough for four bands
+        let side = 288;
+        for (seed, rule) in RULES.iter().enumerate() {
+            let rule: Rule = rule.parse().unwrap();
+            for topology in TOPOLOGIES {
+                let cells = soup(&rule, side, side, seed as u64);
+                let grid = Grid {
+                    cells: &cells,
+                    width: side,
+                    height: side,
+                    topology,
+                };
+                assert!(parallel.install(|| band_rows(side, side)) < side);
+                let region = [(10, 200, 250, 280)];
+                assert!(
+                    serial.install(|| grid.step(&rule)) == parallel.install(|| grid.step(&rule)),
+                    "{} on {:?}",
+                    rule,
+                    topology
+                );
+                assert!(
+                    serial.install(|| grid.step_regions(&rule, &region))
+                        == parallel.install(|| grid.step_regions(&rule, &region)),
+                    "{} on {:?} in a region",
+                    rule,
+                    topology
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,611 @@
+use std::collections::HashMap;
+
+use crate::{Cell, Rule};
+
+// index of a node in the node arena
+pub(crate) type NodeId = u32;
+
+// Once the arena holds this many nodes the unreachable ones are collected
+const GC_THRESHOLD: usize = 1 << 20;
+
+// A quadtree node. A node of level k covers a 2^k x 2^k square, level 0
+// nodes are the single cells (leaves). Nodes are hash-consed so two
+// equal squares are always the same node.
+#[derive(Copy, Clone, Debug)]
+struct Node {
+    level: u8,
+    // the nw, ne, sw and se quadrants; a leaf keeps its state in the first
+    children: [NodeId; 4],
+    population: u64,
+}
+
+// Gosper's HashLife. The pattern lives on an unbounded plane as a
+// canonical quadtree and the RESULT of every node (its center advanced
+// by a power of two generations) is memoized, so repetitive patterns can
+// be stepped billions of generations at once.
+pub struct HashLife {
+    nodes: Vec<Node>,
+    // hash-consing table from (level, children) to the node
+    table: HashMap<(u8, [NodeId; 4]), NodeId>,
+    // the memoized RESULT of a node advanced by 2^j generations
+    results: HashMap<(NodeId, u8), NodeId>,
+    // the empty node of each level
+    empty: Vec<NodeId>,
+    root: NodeId,
+    // the row and col of the top left corner of the root
+    top: i64,
+    left: i64,
+    generation: u64,
+    rule: Rule,
+    gc_threshold: usize,
+}
+
+impl HashLife {
+    pub fn new(rule: Rule) -> HashLife {
+        let mut life = HashLife {
+            nodes: Vec::new(),
+            table: HashMap::new(),
+            results: HashMap::new(),
+            empty: Vec::new(),
+            root: 0,
+            top: 0,
+            left: 0,
+            generation: 0,
+            rule,
+            gc_threshold: GC_THRESHOLD,
+        };
+        life.root = life.empty(3);
+        life
+    }
+
+    // HashLife relies on empty space staying empty and on the 4x4 base
+    // case seeing every neighbour
+    pub fn supports(rule: &Rule) -> bool {
+        !rule.has_b0() && rule.larger().is_none()
+    }
+
+    // build a tree holding the cells of a dense width x height grid
+    // with its top left corner at row 0, col 0
+    pub fn from_cells(rule: Rule, cells: &[Cell], width: u32, height: u32) -> HashLife {
+        let mut life = HashLife::new(rule);
+        let mut level = 3;
+        while (1u64 << level) < width.max(height) as u64 {
+            level += 1;
+        }
+        life.root = life.build(level, 0, 0, cells, width, height);
+        life
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn set_generation(&mut self, generation: u64) {
+        self.generation = generation;
+    }
+
+    pub fn population(&self) -> u64 {
+        self.nodes[self.root as usize].population
+    }
+
+    // changing the rule invalidates every memoized result
+    pub fn set_rule(&mut self, rule: Rule) {
+        self.rule = rule;
+        self.results.clear();
+    }
+
+    // move the whole pattern by a number of rows and cols
+    pub fn shift(&mut self, rows: i64, cols: i64) {
+        self.top += rows;
+        self.left += cols;
+    }
+
+    // The smallest (top, left, bottom, right) rectangle holding every
+    // live cell, bottom and right being exclusive. The box of each node
+    // is only worked out once however often it appears in the tree.
+    pub fn bounding_box(&self) -> Option<(i64, i64, i64, i64)> {
+        let mut memo = HashMap::new();
+        self.node_box(self.root, &mut memo)
+            .map(|(t, l, b, r)| (self.top + t, self.left + l, self.top + b, self.left + r))
+    }
+
+    fn node_box(
+        &self,
+        node: NodeId,
+        memo: &mut HashMap<NodeId, Option<(i64, i64, i64, i64)>>,
+    ) -> Option<(i64, i64, i64, i64)> {
+        let n = self.nodes[node as usize];
+        if n.population == 0 {
+            return None;
+        }
+        if n.level == 0 {
+            return Some((0, 0, 1, 1));
+        }
+        if let Some(&found) = memo.get(&node) {
+            return found;
+        }
+        let half = 1i64 << (n.level - 1);
+        let mut found: Option<(i64, i64, i64, i64)> = None;
+        for (quadrant, &child) in n.children.iter().enumerate() {
+            let (dr, dc) = ((quadrant / 2) as i64 * half, (quadrant % 2) as i64 * half);
+            if let Some((t, l, b, r)) = self.node_box(child, memo) {
+                let (t, l, b, r) = (t + dr, l + dc, b + dr, r + dc);
+                found = Some(match found {
+                    None => (t, l, b, r),
+                    Some((ft, fl, fb, fr)) => (ft.min(t), fl.min(l), fb.max(b), fr.max(r)),
+                });
+            }
+        }
+        memo.insert(node, found);
+        found
+    }
+
+    // get the state of the cell at row, col
+    pub fn get(&self, row: i64, col: i64) -> u8 {
+        let level = self.nodes[self.root as usize].level;
+        let size = 1i64 << level;
+        let (mut row, mut col) = (row - self.top, col - self.left);
+        if row < 0 || col < 0 || row >= size || col >= size {
+            return 0;
+        }
+        let mut node = self.nodes[self.root as usize];
+        let mut half = size / 2;
+        while node.level > 0 {
+            if node.population == 0 {
+                return 0;
+            }
+            let quadrant = (row >= half) as usize * 2 + (col >= half) as usize;
+            row %= half.max(1);
+            col %= half.max(1);
+            half /= 2;
+            node = self.nodes[node.children[quadrant] as usize];
+        }
+        node.children[0] as u8
+    }
+
+    // set the state of the cell at row, col, growing the tree when needed
+    pub fn set(&mut self, row: i64, col: i64, state: u8) {
+        loop {
+            let size = 1i64 << self.nodes[self.root as usize].level;
+            let (r, c) = (row - self.top, col - self.left);
+            if r >= 0 && c >= 0 && r < size && c < size {
+                break;
+            }
+            self.expand();
+        }
+        let leaf = self.leaf(state);
+        self.root = self.set_in(self.root, row - self.top, col - self.left, leaf);
+    }
+
+    // copy the window with its top left corner at row, col into a dense grid
+    pub fn fill_window(&self, row: i64, col: i64, width: u32, height: u32, cells: &mut [Cell]) {
+        for cell in cells.iter_mut() {
+            *cell = Cell::DEAD;
+        }
+        let window = Window {
+            row,
+            col,
+            width: width as i64,
+            height: height as i64,
+        };
+        self.fill(self.root, self.top, self.left, &window, cells);
+    }
+
+    // Advance the pattern by 2^exponent generations
+    pub fn step(&mut self, exponent: u32) {
+        let exponent = exponent.min(60) as u8;
+        // Grow the root until it is big enough to hold the pattern after
+        // 2^exponent generations; the pattern must sit in the middle
+        // quarter so nothing can travel out of the RESULT square.
+        loop {
+            let root = self.nodes[self.root as usize];
+            if root.level >= exponent + 3 {
+                let inner = self.center(self.root);
+                let inner = self.center(inner);
+                if self.nodes[inner as usize].population == root.population {
+                    break;
+                }
+            }
+            self.expand();
+        }
+        let level = self.nodes[self.root as usize].level;
+        self.root = self.advance(self.root, exponent);
+        // the result is the center of the old root
+        let shift = 1i64 << (level - 2);
+        self.top += shift;
+        self.left += shift;
+        self.generation += 1u64 << exponent;
+        // keep the root at least 8x8
+        while self.nodes[self.root as usize].level < 3 {
+            self.expand();
+        }
+
+        if self.nodes.len() > self.gc_threshold {
+            self.collect_garbage();
+            // keep some headroom when most of the nodes are still in use
+            if self.nodes.len() > self.gc_threshold / 2 {
+                self.gc_threshold *= 2;
+            }
+        }
+    }
+
+    // The raw node api the pattern formats build and walk trees with.
+    // A new root is placed with its top left corner at row, col.
+    pub(crate) fn set_root(&mut self, root: NodeId, row: i64, col: i64) {
+        self.root = root;
+        self.top = row;
+        self.left = col;
+        // the pattern formats can hand over a tiny root, grow it
+        // keeping the top left corner in place
+        while self.nodes[self.root as usize].level < 3 {
+            let e = self.empty(self.nodes[self.root as usize].level);
+            self.root = self.join(self.root, e, e, e);
+        }
+    }
+
+    pub(crate) fn root(&self) -> NodeId {
+        self.root
+    }
+
+    pub(crate) fn level(&self, node: NodeId) -> u8 {
+        self.nodes[node as usize].level
+    }
+
+    pub(crate) fn node_population(&self, node: NodeId) -> u64 {
+        self.nodes[node as usize].population
+    }
+
+    // the state of a leaf
+    pub(crate) fn state(&self, leaf: NodeId) -> u8 {
+        self.nodes[leaf as usize].children[0] as u8
+    }
+
+    // find or create the node with the given level and children
+    fn intern(&mut self, level: u8, children: [NodeId; 4]) -> NodeId {
+        if let Some(&id) = self.table.get(&(level, children)) {
+            return id;
+        }
+        let population = if level == 0 {
+            (children[0] != 0) as u64
+        } else {
+            children
+                .iter()
+                .fold(0u64, |sum, &c| sum.saturating_add(self.nodes[c as usize].population))
+        };
+        let id = self.nodes.len() as NodeId;
+        self.nodes.push(Node {
+            level,
+            children,
+            population,
+        });
+        self.table.insert((level, children), id);
+        id
+    }
+
+    pub(crate) fn leaf(&mut self, state: u8) -> NodeId {
+        self.intern(0, [state as NodeId, 0, 0, 0])
+    }
+
+    pub(crate) fn join(&mut self, nw: NodeId, ne: NodeId, sw: NodeId, se: NodeId) -> NodeId {
+        let level = self.nodes[nw as usize].level + 1;
+        self.intern(level, [nw, ne, sw, se])
+    }
+
+    pub(crate) fn empty(&mut self, level: u8) -> NodeId {
+        while self.empty.len() <= level as usize {
+            let id = match self.empty.last() {
+                None => self.leaf(0),
+                Some(&e) => self.join(e, e, e, e),
+            };
+            self.empty.push(id);
+        }
+        self.empty[level as usize]
+    }
+
+    pub(crate) fn children(&self, node: NodeId) -> [NodeId; 4] {
+        self.nodes[node as usize].children
+    }
+
+    // the level k-1 square in the middle of a level k node
+    fn center(&mut self, node: NodeId) -> NodeId {
+        let [nw, ne, sw, se] = self.children(node);
+        let nw = self.children(nw)[3];
+        let ne = self.children(ne)[2];
+        let sw = self.children(sw)[1];
+        let se = self.children(se)[0];
+        self.join(nw, ne, sw, se)
+    }
+
+    // double the size of the root keeping the pattern in the middle
+    fn expand(&mut self) {
+        let level = self.nodes[self.root as usize].level;
+        let e = self.empty(level - 1);
+        let [nw, ne, sw, se] = self.children(self.root);
+        let nw = self.join(e, e, e, nw);
+        let ne = self.join(e, e, ne, e);
+        let sw = self.join(e, sw, e, e);
+        let se = self.join(se, e, e, e);
+        self.root = self.join(nw, ne, sw, se);
+        let shift = 1i64 << (level - 1);
+        self.top -= shift;
+        self.left -= shift;
+    }
+
+    fn build(&mut self, level: u8, row: u32, col: u32, cells: &[Cell], width: u32, height: u32) -> NodeId {
+        if row >= height || col >= width {
+            return self.empty(level);
+        }
+        if level == 0 {
+            let state = cells[(row * width + col) as usize].state();
+            return self.leaf(state);
+        }
+        let half = 1u32 << (level - 1);
+        let nw = self.build(level - 1, row, col, cells, width, height);
+        let ne = self.build(level - 1, row, col + half, cells, width, height);
+        let sw = self.build(level - 1, row + half, col, cells, width, height);
+        let se = self.build(level - 1, row + half, col + half, cells, width, height);
+        self.join(nw, ne, sw, se)
+    }
+
+    fn set_in(&mut self, node: NodeId, row: i64, col: i64, leaf: NodeId) -> NodeId {
+        let level = self.nodes[node as usize].level;
+        if level == 0 {
+            return leaf;
+        }
+        let half = 1i64 << (level - 1);
+        let mut children = self.children(node);
+        let quadrant = (row >= half) as usize * 2 + (col >= half) as usize;
+        children[quadrant] = self.set_in(children[quadrant], row % half, col % half, leaf);
+        self.intern(level, children)
+    }
+
+    fn fill(&self, node: NodeId, top: i64, left: i64, window: &Window, cells: &mut [Cell]) {
+        let n = self.nodes[node as usize];
+        let size = 1i64 << n.level;
+        // skip empty nodes and nodes that miss the window
+        if n.population == 0
+            || top >= window.row + window.height
+            || left >= window.col + window.width
+            || top + size <= window.row
+            || left + size <= window.col
+        {
+            return;
+        }
+        if n.level == 0 {
+            let idx = (top - window.row) * window.width + (left - window.col);
+            cells[idx as usize] = Cell::new(n.children[0] as u8);
+            return;
+        }
+        let half = size / 2;
+        self.fill(n.children[0], top, left, window, cells);
+        self.fill(n.children[1], top, left + half, window, cells);
+        self.fill(n.children[2], top + half, left, window, cells);
+        self.fill(n.children[3], top + half, left + half, window, cells);
+    }
+
+    // RESULT: the level k-1 center of a level k node advanced by
+    // 2^j generations, where j <= k - 2
+    fn advance(&mut self, node: NodeId, j: u8) -> NodeId {
+        let n = self.nodes[node as usize];
+        if n.population == 0 {
+            return self.empty(n.level - 1);
+        }
+        if let Some(&result) = self.results.get(&(node, j)) {
+            return result;
+        }
+        let result = if n.level == 2 {
+            self.advance_base(node)
+        } else {
+            self.advance_recursive(node, j)
+        };
+        self.results.insert((node, j), result);
+        result
+    }
+
+    // a 4x4 node is advanced a single generation by brute force
+    fn advance_base(&mut self, node: NodeId) -> NodeId {
+        let mut grid = [[0u8; 4]; 4];
+        for (quadrant, &child) in self.children(node).iter().enumerate() {
+            for (i, &leaf) in self.children(child).iter().enumerate() {
+                let row = (quadrant / 2) * 2 + i / 2;
+                let col = (quadrant % 2) * 2 + i % 2;
+                grid[row][col] = self.nodes[leaf as usize].children[0] as u8;
+            }
+        }
+        let mut next = [0u8; 4];
+        for (i, state) in next.iter_mut().enumerate() {
+            let (row, col) = (1 + i / 2, 1 + i % 2);
+            if let Some(table) = self.rule.table() {
+                let mut around = [0u8; 8];
+                for (state, &(delta_row, delta_col)) in around.iter_mut().zip(table.offsets()) {
+                    let (r, c) = (row as i32 + delta_row, col as i32 + delta_col);
+                    *state = grid[r as usize][c as usize];
+                }
+                *state = table.next(grid[row][col], &around[..table.offsets().len()]);
+                continue;
+            }
+            let mut neighbors = 0;
+            for (bit, &(delta_row, delta_col)) in self.rule.neighborhood().offsets().iter().enumerate() {
+                let r = (row as i32 + delta_row) as usize;
+                let c = (col as i32 + delta_col) as usize;
+                neighbors |= ((grid[r][c] == 1) as u8) << bit;
+            }
+            *state = self.rule.next_cell_around(Cell::new(grid[row][col]), neighbors).state();
+        }
+        let leaves = [
+            self.leaf(next[0]),
+            self.leaf(next[1]),
+            self.leaf(next[2]),
+            self.leaf(next[3]),
+        ];
+        self.join(leaves[0], leaves[1], leaves[2], leaves[3])
+    }
+
+    fn advance_recursive(&mut self, node: NodeId, j: u8) -> NodeId {
+        let level = self.nodes[node as usize].level;
+        let [nw, ne, sw, se] = self.children(node);
+        let [_, nw_ne, nw_sw, nw_se] = self.children(nw);
+        let [ne_nw, _, ne_sw, ne_se] = self.children(ne);
+        let [sw_nw, sw_ne, _, sw_se] = self.children(sw);
+        let [se_nw, se_ne, se_sw, _] = self.children(se);
+
+        // the nine overlapping level k-1 squares
+        let squares = [
+            nw,
+            self.join(nw_ne, ne_nw, nw_se, ne_sw),
+            ne,
+            self.join(nw_sw, nw_se, sw_nw, sw_ne),
+            self.join(nw_se, ne_sw, sw_ne, se_nw),
+            self.join(ne_sw, ne_se, se_nw, se_ne),
+            sw,
+            self.join(sw_ne, se_nw, sw_se, se_sw),
+            se,
+        ];
+        // At full speed both halves advance 2^(k-3) generations, otherwise
+        // the first half only takes the centers and the second half does
+        // all of the 2^j generations.
+        let full_speed = j == level - 2;
+        let mut parts = [0; 9];
+        for (part, &square) in parts.iter_mut().zip(squares.iter()) {
+            *part = if full_speed {
+                self.advance(square, level - 3)
+            } else {
+                self.center(square)
+            };
+        }
+        let second = if full_speed { level - 3 } else { j };
+        let quads = [
+            self.join(parts[0], parts[1], parts[3], parts[4]),
+            self.join(parts[1], parts[2], parts[4], parts[5]),
+            self.join(parts[3], parts[4], parts[6], parts[7]),
+            self.join(parts[4], parts[5], parts[7], parts[8]),
+        ];
+        let nw = self.advance(quads[0], second);
+        let ne = self.advance(quads[1], second);
+        let sw = self.advance(quads[2], second);
+        let se = self.advance(quads[3], second);
+        self.join(nw, ne, sw, se)
+    }
+
+    // Copy the nodes reachable from the root into a fresh arena, dropping
+    // everything else. Memoized results survive when both ends survive.
+    fn collect_garbage(&mut self) {
+        let mut remap: HashMap<NodeId, NodeId> = HashMap::new();
+        let mut nodes = Vec::new();
+        let mut table = HashMap::new();
+        // children are always copied before their parents
+        let mut stack = vec![(self.root, false)];
+        while let Some((id, expanded)) = stack.pop() {
+            if remap.contains_key(&id) {
+                continue;
+            }
+            let node = self.nodes[id as usize];
+            if node.level > 0 && !expanded {
+                stack.push((id, true));
+                for &child in node.children.iter() {
+                    if !remap.contains_key(&child) {
+                        stack.push((child, false));
+                    }
+                }
+                continue;
+            }
+            let mut children = node.children;
+            if node.level > 0 {
+                for child in children.iter_mut() {
+                    *child = remap[child];
+                }
+            }
+            let new_id = nodes.len() as NodeId;
+            nodes.push(Node { children, ..node });
+            table.insert((node.level, children), new_id);
+            remap.insert(id, new_id);
+        }
+        self.results = self
+            .results
+            .iter()
+            .filter_map(|(&(node, j), result)| {
+                Some(((*remap.get(&node)?, j), *remap.get(result)?))
+            })
+            .collect();
+        self.root = remap[&self.root];
+        self.nodes = nodes;
+        self.table = table;
+        self.empty.clear();
+    }
+}
+
+// the dense window being filled from the tree
+struct Window {
+    row: i64,
+    col: i64,
+    width: i64,
+    height: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Backend, Universe};
+
+    // a 32x32 soup in the middle of a 256x256 plane; nothing in it moves
+    // faster than a cell a generation, so in 96 generations it stays off
+    // the edges and the grid sees all of what HashLife's plane holds
+    fn soup(rule: &str, seed: u64) -> Universe {
+        let mut universe = Universe::new();
+        universe.set_width(256);
+        universe.set_height(256);
+        universe.set_rule(&format!("{}:P", rule)).unwrap();
+        universe.randomize_region(112, 112, 32, 32, seed, 0.4).unwrap();
+        universe
+    }
+
+    // Life-like, isotropic, Generations and hexagonal rules
+    const RULES: [&str; 6] = [
+        "B3/S23",
+        "B36/S23",
+        "B35678/S5678",
+        "B2-a/S12",
+        "B2/S/C3",
+        "B2/S34H",
+    ];
+
+    #[test]
+    fn ticks_like_dense() {
+        for (seed, rule) in RULES.iter().enumerate() {
+            let mut dense = soup(rule, seed as u64);
+            let mut hashlife = soup(rule, seed as u64);
+            hashlife.set_backend(Backend::HashLife).unwrap();
+            for generation in 1..=48 {
+                dense.tick();
+                hashlife.tick();
+                assert!(dense.get_cells() == hashlife.get_cells(), "{} at {}", rule, generation);
+            }
+        }
+    }
+
+    #[test]
+    fn steps_like_dense() {
+        for (seed, rule) in RULES.iter().enumerate() {
+            let mut dense = soup(rule, seed as u64);
+            let mut hashlife = soup(rule, seed as u64);
+            hashlife.set_backend(Backend::HashLife).unwrap();
+            // 96 generations in three leaps against one tick at a time
+            for exponent in [5, 6, 5] {
+                hashlife.step(exponent).unwrap();
+                for _ in 0..1 << exponent {
+                    dense.tick();
+                }
+                assert!(dense.get_cells() == hashlife.get_cells(), "{} at 2^{}", rule, exponent);
+            }
+            assert_eq!(dense.generation(), hashlife.generation());
+        }
+    }
+
+    #[test]
+    fn rejects_steps_too_big_to_take() {
+        let mut dense = soup("B3/S23", 0);
+        assert!(dense.step(64).is_err());
+        assert_eq!(dense.generation(), 0);
+        dense.set_backend(Backend::HashLife).unwrap();
+        assert!(dense.step(61).is_err());
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::VecDeque;
+
+use crate::Cell;
+
+// how the cells before a step are kept
+enum Change {
+    // a full copy of the cells
+    Snapshot(Vec<Cell>),
+    // only the (index, old cell) of the cells the step changed
+    Delta(Vec<(u32, Cell)>),
+}
+
+enum Entry {
+    // a tick or a HashLife step, with the generation before it
+    Step { generation: u64, change: Change },
+    // a toggle_cell on the cell at the flat index, with the cell before
+    Edit { idx: u32, old: Cell },
+}
+
+// A bounded ring of the recent past of a universe; the ticks so they
+// can be stepped back and the edits so they can be undone and redone.
+pub struct History {
+    entries: VecDeque<Entry>,
+    // the (index, edited cell) of the edits undone since the last tick
+    // or edit
+    redo: Vec<(u32, Cell)>,
+    // the number of entries kept, 0 turns the history off
+    depth: usize,
+    // keep deltas instead of full snapshots
+    compress: bool,
+}
+
+impl History {
+    pub fn new(depth: usize, compress: bool) -> History {
+        History {
+            entries: VecDeque::new(),
+            redo: Vec::new(),
+            depth,
+            compress,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.depth > 0
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn set_depth(&mut self, depth: usize) {
+        self.depth = depth;
+        self.trim();
+    }
+
+    pub fn set_compress(&mut self, compress: bool) {
+        self.compress = compress;
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.redo.clear();
+    }
+
+    // the oldest generation that can still be stepped back to
+    pub fn oldest_generation(&self) -> Option<u64> {
+        self.entries.iter().find_map(|entry| match entry {
+            Entry::Step { generation, .. } => Some(*generation),
+            Entry::Edit { .. } => None,
+        })
+    }
+
+    // record a step given the cells before and after it
+    pub fn record_step(&mut self, before: Vec<Cell>, after: &[Cell], generation: u64) {
+        if !self.is_enabled() {
+            return;
+        }
+        let change = if self.compress {
+            let delta = before
+                .iter()
+                .zip(after.iter())
+                .enumerate()
+                .filter(|(_, (old, new))| old != new)
+                .map(|(idx, (&old, _))| (idx as u32, old))
+                .collect();
+            Change::Delta(delta)
+        } else {
+            Change::Snapshot(before)
+        };
+        self.entries.push_back(Entry::Step { generation, change });
+        self.redo.clear();
+        self.trim();
+    }
+
+    pub fn record_edit(&mut self, idx: u32, old: Cell) {
+        if !self.is_enabled() {
+            return;
+        }
+        self.entries.push_back(Entry::Edit { idx, old });
+        self.redo.clear();
+        self.trim();
+    }
+
+    // Undo the edits made since the last step, then the step itself.
+    // Returns the generation stepped back to.
+    pub fn step_back(&mut self, cells: &mut [Cell]) -> Option<u64> {
+        self.oldest_generation()?;
+        while let Some(entry) = self.entries.pop_back() {
+            match entry {
+                Entry::Edit { idx, old } => cells[idx as usize] = old,
+                Entry::Step { generation, change } => {
+                    match change {
+                        Change::Snapshot(before) => cells.copy_from_slice(&before),
+                        Change::Delta(delta) => {
+                            for (idx, old) in delta {
+                                cells[idx as usize] = old;
+                            }
+                        }
+                    }
+                    self.redo.clear();
+                    return Some(generation);
+                }
+            }
+        }
+        None
+    }
+
+    // undo the last edit when there was no step after it
+    pub fn undo(&mut self, cells: &mut [Cell]) -> Option<u32> {
+        match self.entries.back() {
+            Some(&Entry::Edit { idx, old }) => {
+                self.entries.pop_back();
+                self.redo.push((idx, cells[idx as usize]));
+                cells[idx as usize] = old;
+                Some(idx)
+            }
+            _ => None,
+        }
+    }
+
+    // redo the last undone edit
+    pub fn redo(&mut self, cells: &mut [Cell]) -> Option<u32> {
+        let (idx, cell) = self.redo.pop()?;
+        let old = std::mem::replace(&mut cells[idx as usize], cell);
+        self.entries.push_back(Entry::Edit { idx, old });
+        self.trim();
+        Some(idx)
+    }
+
+    // drop the oldest entries past the depth
+    fn trim(&mut self) {
+        while self.entries.len() > self.depth {
+            self.entries.pop_front();
+        }
+    }
+}
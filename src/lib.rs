@@ -1,8 +1,47 @@
 mod utils;
+mod active;
+mod analysis;
+mod census;
+pub mod formats;
+mod gif;
+mod grid;
+mod hashlife;
+mod history;
+mod packed;
+mod png;
+mod random;
+mod record;
+mod render;
+mod rule;
+#[cfg(feature = "simd")]
+mod simd;
+mod sparse;
+mod topology;
+mod viewport;
 
 use wasm_bindgen::prelude::*;
 use std::fmt;
 
+use active::ActiveTiles;
+use grid::Grid;
+
+pub use active::TickStats;
+pub use analysis::{PeriodInfo, PeriodKind};
+pub use formats::Pattern;
+pub use hashlife::HashLife;
+pub use history::History;
+pub use packed::PackedGrid;
+pub use random::Random;
+pub use record::{Recorder, RecordingFormat};
+pub use render::Renderer;
+pub use rule::{Larger, LargerShape, Neighborhood, Rule, RuleTable};
+pub use sparse::SparseGrid;
+pub use topology::Topology;
+// js starts the worker pool the parallel feature steps on with
+// `await initThreadPool(navigator.hardwareConcurrency)` before the first tick
+#[cfg(all(feature = "parallel", target_arch = "wasm32"))]
+pub use wasm_bindgen_rayon::init_thread_pool;
+
 extern crate web_sys;
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
 // allocator.
@@ -11,26 +50,69 @@ extern crate web_sys;
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
 // implementation of Game of life cell
-#[wasm_bindgen]
-#[repr(u8)] // reprecent the cell as a u8
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
-pub enum Cell {
-    // The cell has two states; dead: 0, alive: 1
-    Dead = 0,
-    Alive = 1,
-}
+// The cell holds its state number; dead: 0, alive: 1 and on a
+// Generations rule the dying (refractory) states 2, 3, .. after that
+#[repr(transparent)] // reprecent the cell as a u8
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Cell(u8);
 
-// Implement a toggle method so js can toggle cells by clicking
 impl Cell {
+    pub const DEAD: Cell = Cell(0);
+    pub const ALIVE: Cell = Cell(1);
+
+    pub fn new(state: u8) -> Cell {
+        Cell(state)
+    }
+    // the state number, as js sees it in the cells buffer
+    pub fn state(self) -> u8 {
+        self.0
+    }
+    // Implement a toggle method so js can toggle cells by clicking;
+    // a dead cell comes alive and any other state dies
     fn toggle(&mut self) {
-        // match the current state and invert
-        // and change the self state
-        *self = match *self {
-            Cell::Alive => Cell::Dead,
-            Cell::Dead => Cell::Alive,
-        };
+        *self = if *self == Cell::DEAD { Cell::ALIVE } else { Cell::DEAD };
     }
 }
+// The engine that steps the universe forward. The dense backend scans
+// every cell of the grid each tick; the HashLife backend keeps the pattern
+// in a memoized quadtree on an unbounded plane and the grid becomes a
+// window onto it (so the topology is ignored). The packed backend keeps
+// 64 cells to a word and ticks them with bitwise adders. The sparse
+// backend keeps the live 64x64 tiles of an unbounded plane, the grid
+// being a window onto it like on HashLife.
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Backend {
+    Dense = 0,
+    HashLife = 1,
+    Packed = 2,
+    Sparse = 3,
+}
+
+// the state a backend keeps next to the dense cells
+enum Engine {
+    Dense,
+    HashLife(HashLife),
+    Packed(PackedGrid),
+    Sparse(SparseGrid),
+}
+
+// the largest window a macrocell universe opens onto its pattern
+const MAX_WINDOW: i64 = 2048;
+
+// the most cells a universe built from a pattern may hold, a byte each
+// so 256MB of wasm's 4GB
+const MAX_CELLS: u32 = 1 << 28;
+
+// the dying states of a Generations rule fade from this grey to white
+const DYING_SHADE: u8 = 96;
+
+// the largest step HashLife takes, keeping the generation count in a u64
+const MAX_LEAP: u32 = 60;
+// and the largest the other backends tick through, 2^32 ticks already
+// being hours of work on any board worth looking at
+const MAX_TICKED_STEP: u32 = 32;
+
 // Lets define the universe, the universe has a
 // height, width and a vector of cells
 #[wasm_bindgen]
@@ -38,6 +120,34 @@ pub struct Universe {
     width: u32,
     height: u32,
     cells: Vec<Cell>,
+    // the birth/survival rule the universe evolves with
+    rule: Rule,
+    // how the edges of the universe are joined
+    topology: Topology,
+    // the backend stepping the universe
+    engine: Engine,
+    // the number of generations since the universe was made
+    generation: u64,
+    // the recent steps and edits, for stepping back and undo
+    history: History,
+    // the (row, col) of the grid's top left cell on the plane of the
+    // unbounded backends
+    viewport: (i64, i64),
+    // the tiles of the grid which changed in the last tick, so the dense
+    // backend can skip the still parts
+    active: ActiveTiles,
+    // how much of the board the last tick stepped
+    stats: TickStats,
+    // the flat indices of the cells the last tick (or step) changed,
+    // followed by the ones toggled since
+    changed: Vec<u32>,
+    // the cells were replaced wholesale since the last tick
+    all_changed: bool,
+    // counts the ticks, steps and edits, so a renderer can tell whether
+    // the changed cells cover everything since it last drew
+    version: u64,
+    // the version the changed cells are listed from
+    changed_since: u64,
 }
 
 // impement the fmt::Display trait on universe
@@ -54,14 +164,14 @@ impl fmt::Display for Universe {
         for line in cells.as_slice().chunks(self.width as usize) {
             for &cell in line {
                 // cell refers to cell enum type so we can compare
-                let symbol = if cell == Cell::Alive {'◼'} else {'◻'};
+                let symbol = if cell == Cell::ALIVE {'◼'} else {'◻'};
                 // write the symbol using write macro
                 // write and unwrap the results for exceptions ("?")
                 write!(f, "{}", symbol)?;
             }
             // write a new line
             // write and unwrap the results for exceptions ("?")
-            write!(f, "\n")?;
+            writeln!(f)?;
         }
         // Return an ok result
         // when all goes good
@@ -78,32 +188,102 @@ impl fmt::Display for Universe {
 impl Universe {
     // Universe constructor
     pub fn new() -> Universe {
-        // Initialize a panick hook in the constructor
-        // so we can console log the panicks
-        utils::set_panic_hook();
-
         let height = 64;
         let width = 64;
         // Create an initial cell pattern for the universe
         let cells = (0..width * height)
             .map(|i| {
                 if i % 2 == 0 || i % 7 == 0 {
-                    // return Cell::Alive so the
+                    // return Cell::ALIVE so the
                     // collector collects it
-                    Cell::Alive
+                    Cell::ALIVE
                 }
                 else {
-                    // return Cell::Dead so the
+                    // return Cell::DEAD so the
                     // collector collects it
-                    Cell::Dead
+                    Cell::DEAD
                 }
             }).collect();
         
         // Initalize the new universe
-        Universe {
-            width,
-            height,
-            cells,
+        let mut universe = Universe::blank(width, height);
+        universe.cells = cells;
+        universe
+    }
+    // build a universe from an RLE pattern, the universe is sized to
+    // the pattern or to the bounded grid given with its rule
+    pub fn from_rle(rle: &str) -> Result<Universe, String> {
+        Universe::from_pattern(&formats::rle::parse(rle)?)
+    }
+    // place an RLE pattern with its top left corner at row, col;
+    // the rule of the universe is left as it is
+    pub fn load_rle_at(&mut self, row: u32, col: u32, rle: &str) -> Result<(), String> {
+        self.load_pattern_at(row, col, &formats::rle::parse(rle)?)
+    }
+    // write the live cells out as an RLE pattern
+    pub fn to_rle(&self) -> String {
+        formats::rle::write(&self.to_pattern())
+    }
+    // build a universe from a plaintext (.cells) pattern, the text
+    // `render` writes can be read back in this way too
+    pub fn from_plaintext(text: &str) -> Result<Universe, String> {
+        Universe::from_pattern(&formats::plaintext::parse(text)?)
+    }
+    // place a plaintext (.cells) pattern with its top left corner at row, col
+    pub fn load_plaintext_at(&mut self, row: u32, col: u32, text: &str) -> Result<(), String> {
+        self.load_pattern_at(row, col, &formats::plaintext::parse(text)?)
+    }
+    // write the live cells out as a plaintext (.cells) pattern
+    pub fn to_plaintext(&self) -> String {
+        formats::plaintext::write(&self.to_pattern())
+    }
+    // place a Life 1.06 pattern with its top left live cell at row, col
+    pub fn load_life106_at(&mut self, row: u32, col: u32, text: &str) -> Result<(), String> {
+        self.load_pattern_at(row, col, &formats::life::parse_106(text)?)
+    }
+    // write the live cells out as a Life 1.06 coordinate list
+    pub fn to_life106(&self) -> String {
+        formats::life::write_106(&self.to_pattern())
+    }
+    // place a Life 1.05 pattern with its top left live cell at row, col
+    pub fn load_life105_at(&mut self, row: u32, col: u32, text: &str) -> Result<(), String> {
+        self.load_pattern_at(row, col, &formats::life::parse_105(text)?)
+    }
+    // write the live cells out as a Life 1.05 pattern, which fails for
+    // rules that aren't Life-like as the format has no way to give them
+    pub fn to_life105(&self) -> Result<String, String> {
+        formats::life::write_105(&self.to_pattern())
+    }
+    // Build a universe from a Golly macrocell (.mc) file. The pattern
+    // stays in a HashLife quadtree, so it can be far larger than the
+    // grid; the universe is a window of at most 2048x2048 cells onto the
+    // top left of the pattern.
+    pub fn from_macrocell(text: &str) -> Result<Universe, String> {
+        let (mut life, rule) = formats::macrocell::parse(text)?;
+        let (top, left, bottom, right) = life.bounding_box().unwrap_or((0, 0, 1, 1));
+        life.shift(-top, -left);
+        let width = (right - left).clamp(1, MAX_WINDOW) as u32;
+        let height = (bottom - top).clamp(1, MAX_WINDOW) as u32;
+        let mut universe = Universe::blank(width, height);
+        if let Some(rule) = rule {
+            universe.set_rule(&rule)?;
+        }
+        life.fill_window(0, 0, width, height, &mut universe.cells);
+        universe.generation = life.generation();
+        universe.engine = Engine::HashLife(life);
+        Ok(universe)
+    }
+    // Write the universe out as a Golly macrocell (.mc) file. On the
+    // HashLife backend this is the whole quadtree, not just the window.
+    pub fn to_macrocell(&self) -> String {
+        match &self.engine {
+            Engine::HashLife(life) => formats::macrocell::write(life, &self.rule.to_string()),
+            _ => {
+                let life =
+                    HashLife::from_cells(self.rule.clone(), &self.cells, self.width, self.height);
+                let rule = format!("{}:{}", self.rule, self.topology());
+                formats::macrocell::write(&life, &rule)
+            }
         }
     }
     // get width
@@ -123,24 +303,215 @@ impl Universe {
     pub fn cells(&self) -> *const Cell {
         // return a pointer to the start of the cell vector
         // js consumes the pointer from the wasm linear memory
-        // and render it on the canvas.
+        // and render it on the canvas. Each cell is a byte holding
+        // its state number, 0 dead, 1 alive and 2.. dying
         self.cells.as_ptr()
     }
+    // the number of states a cell can be in, more than 2 on a
+    // Generations rule
+    pub fn states(&self) -> u8 {
+        self.rule.states()
+    }
+    // the colour to draw each state in as r, g, b bytes; what the rule
+    // table's @COLORS gives or else white for dead, black for alive and
+    // greys fading to white for the dying states
+    pub fn palette(&self) -> Vec<u8> {
+        let states = self.rule.states();
+        let mut palette = Vec::with_capacity(states as usize * 3);
+        for state in 0..states {
+            let default = match state {
+                0 => (255, 255, 255),
+                1 => (0, 0, 0),
+                _ => {
+                    let dying = (state - 2) as u32;
+                    let fade = (255 - DYING_SHADE) as u32 * dying / (states as u32 - 2).max(1);
+                    let shade = DYING_SHADE + fade as u8;
+                    (shade, shade, shade)
+                }
+            };
+            let table = self.rule.table().and_then(|table| table.color(state));
+            let (r, g, b) = table.unwrap_or(default);
+            palette.extend_from_slice(&[r, g, b]);
+        }
+        palette
+    }
 
     // lets set some setters and getters to have different size universes
     pub fn set_width(&mut self, width: u32) {
         self.width = width;
         // initiate all the cells to dead
-        self.cells = (0..width * self.height).map(|_i| Cell::Dead).collect();
+        self.cells = (0..width * self.height).map(|_i| Cell::DEAD).collect();
+        self.cells_replaced();
     }
     pub fn set_height(&mut self, height: u32) {
         self.height = height;
         // initiate all the cells to dead
-        self.cells = (0..self.width * height).map(|_i| Cell::Dead).collect();
+        self.cells = (0..self.width * height).map(|_i| Cell::DEAD).collect();
+        self.cells_replaced();
+    }
+    // get the rule as a B/S rulestring
+    pub fn rule(&self) -> String {
+        self.rule.to_string()
+    }
+    // set the rule from a rulestring such as "B36/S23" or "23/3",
+    // a malformed rulestring is reported back to js as an error.
+    // A Golly bounded grid suffix ("B3/S23:P") also sets the topology
+    pub fn set_rule(&mut self, rule: &str) -> Result<(), String> {
+        let (rule, topology) = match rule.split_once(':') {
+            Some((rule, topology)) => (rule.parse()?, Some(topology.parse()?)),
+            None => (rule.parse()?, None),
+        };
+        self.replace_rule(rule)?;
+        if let Some(topology) = topology {
+            self.topology = topology;
+        }
+        Ok(())
+    }
+    // set the rule from the text of a Golly .rule file, the rule then
+    // goes by the name given after @RULE
+    pub fn load_rule_table(&mut self, text: &str) -> Result<(), String> {
+        self.replace_rule(Rule::from_table(text)?)
+    }
+    // get the topology as a Golly bounded grid suffix, ie. "T64,64"
+    pub fn topology(&self) -> String {
+        self.topology.to_golly(self.width, self.height)
+    }
+    // set the topology from a Golly bounded grid suffix; "T" torus,
+    // "P" plane, "Tw,0"/"T0,h" cylinders, "K" klein bottle,
+    // "C" cross-surface and "S" sphere. Sizes are ignored apart
+    // from picking the cylinder or the twisted edges.
+    pub fn set_topology(&mut self, topology: &str) -> Result<(), String> {
+        self.topology = topology.parse()?;
+        self.active.mark_all(self.width, self.height);
+        Ok(())
+    }
+    // get the backend stepping the universe
+    pub fn backend(&self) -> Backend {
+        match self.engine {
+            Engine::Dense => Backend::Dense,
+            Engine::HashLife(_) => Backend::HashLife,
+            Engine::Packed(_) => Backend::Packed,
+            Engine::Sparse(_) => Backend::Sparse,
+        }
+    }
+    // switch the backend, the current cells carry over to the new one
+    pub fn set_backend(&mut self, backend: Backend) -> Result<(), String> {
+        if let Some(error) = unsupported(backend, &self.rule) {
+            return Err(error.to_string());
+        }
+        self.engine = self.build_engine(backend);
+        self.active.mark_all(self.width, self.height);
+        Ok(())
+    }
+    // advance the universe by 2^exponent generations; HashLife does this
+    // in one go, the other backends tick that many times. Fails when the
+    // exponent is past MAX_LEAP for HashLife or MAX_TICKED_STEP otherwise
+    pub fn step(&mut self, exponent: u32) -> Result<(), String> {
+        let whole = [(0, 0, self.height, self.width)];
+        match self.backend() {
+            Backend::HashLife => {
+                if exponent > MAX_LEAP {
+                    return Err(format!("HashLife steps at most 2^{} generations", MAX_LEAP));
+                }
+                let before = self.history.is_enabled().then(|| self.cells.clone());
+                let previous = self.cells.clone();
+                if let Engine::HashLife(hashlife) = &mut self.engine {
+                    hashlife.step(exponent);
+                    let (row, col) = self.viewport;
+                    hashlife.fill_window(row, col, self.width, self.height, &mut self.cells);
+                }
+                self.stats = TickStats::default();
+                self.list_changes(&previous, &whole);
+                self.finish_step(before, 1 << exponent);
+            }
+            _ if exponent > MAX_TICKED_STEP => {
+                return Err(format!(
+                    "{:?} ticks at most 2^{} generations a step, use HashLife to go further",
+                    self.backend(),
+                    MAX_TICKED_STEP
+                ));
+            }
+            // a single tick lists its own changes
+            _ if exponent == 0 => self.tick(),
+            _ => {
+                let previous = self.cells.clone();
+                let version = self.version;
+                for _ in 0..1u64 << exponent {
+                    self.tick();
+                }
+                // the changes of the whole step, not just its last tick
+                self.list_changes(&previous, &whole);
+                self.changed_since = version;
+            }
+        }
+        Ok(())
+    }
+    // Pointer to the flat indices (u32) of the cells which changed in the
+    // last tick or step, followed by those toggled (or undone) since, so
+    // js can redraw only them; overlay a Uint32Array of
+    // `changed_cells_len` entries. After `all_cells_changed` the list is
+    // empty and every cell needs redrawing.
+    pub fn changed_cells(&self) -> *const u32 {
+        self.changed.as_ptr()
+    }
+    pub fn changed_cells_len(&self) -> u32 {
+        self.changed.len() as u32
+    }
+    // were the cells replaced wholesale (a pattern loaded, the universe
+    // resized or stepped back, the viewport moved) since the last tick
+    pub fn all_cells_changed(&self) -> bool {
+        self.all_changed
+    }
+    // pointer to the bit-packed cells when running on the packed backend,
+    // null otherwise. Rows are padded to whole u64 words (see
+    // `packed_words_per_row`) and the cell at row, col is bit col % 64 of
+    // word row * packed_words_per_row + col / 64; js can overlay a
+    // BigUint64Array, or a Uint8Array where the cell is bit col % 8 of
+    // byte row * packed_words_per_row * 8 + col / 8.
+    pub fn packed_cells(&self) -> *const u64 {
+        match &self.engine {
+            Engine::Packed(packed) => packed.words().as_ptr(),
+            _ => std::ptr::null(),
+        }
+    }
+    // the number of u64 words in each row of the packed cells
+    pub fn packed_words_per_row(&self) -> u32 {
+        match &self.engine {
+            Engine::Packed(packed) => packed.words_per_row() as u32,
+            _ => 0,
+        }
+    }
+    // fill the whole universe with a random soup, each cell is alive
+    // with a chance of density; the same seed always gives the same soup
+    pub fn randomize(&mut self, seed: u64, density: f64) {
+        self.fill_random(0, 0, self.width, self.height, seed, density);
+    }
+    // fill a width x height region with its top left corner at row, col
+    // with a random soup
+    pub fn randomize_region(
+        &mut self,
+        row: u32,
+        col: u32,
+        width: u32,
+        height: u32,
+        seed: u64,
+        density: f64,
+    ) -> Result<(), String> {
+        if row as u64 + height as u64 > self.height as u64
+            || col as u64 + width as u64 > self.width as u64
+        {
+            return Err(format!(
+                "a {}x{} region doesn't fit at row {}, col {} of a {}x{} universe",
+                width, height, row, col, self.width, self.height
+            ));
+        }
+        self.fill_random(row, col, width, height, seed, density);
+        Ok(())
     }
     // the tick function below modifies a cell for the next tick of the
     // universe; the cell can be die, stay alive or reborn.
-    // the cell modification rules are as follows,
+    // the rule of the universe decides which, for Conway's game of
+    // life (B3/S23) the cell modification rules are as follows,
     
     // Rule 1: Any live cell with fewer than two live neighbours
     // dies, as if caused by underpopulation.
@@ -156,40 +527,144 @@ impl Universe {
 
     // All other cells remain in the same state.
     pub fn tick(&mut self) {
-        // get the flat vect of cells in the universe
-        let mut next = self.cells.clone();
-        // Iterate over the universe grid
-        for row in 0..self.height {
-            for col in 0..self.width {
-                // get the current flat index
-                let idx = self.get_index(row, col);
-                // get the current cell
-                let cell = self.cells[idx];
-                // find the living neighbors
-                let neighbors_alive = self.live_neighbor_count(row, col);
-                // Now do a pattern matching using the current cell and its 
-                // living neighbors to find the next cell state according to the rules
-                let next_cell = match (cell, neighbors_alive) {
-                    // RULE: 1
-                    // if the current cell alive and neighbors < 2 current cell in the
-                    // next tick dies
-                    (Cell::Alive, x) if x < 2 => Cell::Dead,
-                    // RULE: 2
-                    (Cell::Alive, 2) | (Cell::Alive, 3) => Cell::Alive,
-                    // Rule: 3
-                    (Cell::Alive, x) if x > 3 => Cell::Dead,
-                    // Rule: 4
-                    (Cell::Dead, 3) => Cell::Alive,
-                    // the non-exhaustive patter, all the other cells
-                    // in the universe remains the same
-                    (otherwise, _) => otherwise,
-                };
-                // update the state of the cell for the next tick
-                next[idx] = next_cell;
+        // HashLife steps a single generation on its own
+        if let Engine::HashLife(_) = self.engine {
+            self.step(0).expect("a single generation is always a valid step");
+            return;
+        }
+        let before = self.history.is_enabled().then(|| self.cells.clone());
+        self.next_generation();
+        self.finish_step(before, 1);
+    }
+    // how many tiles of the board the last tick stepped and how many it
+    // skipped as nothing near them changed
+    pub fn stats(&self) -> TickStats {
+        self.stats
+    }
+    // the number of live and dying cells; on the unbounded backends this
+    // counts the whole plane and not just the grid
+    pub fn population(&self) -> u64 {
+        match &self.engine {
+            Engine::HashLife(hashlife) => hashlife.population(),
+            Engine::Sparse(sparse) => sparse.population(),
+            _ => self.cells.iter().filter(|&&cell| cell != Cell::DEAD).count() as u64,
+        }
+    }
+    // get the number of generations the universe has been stepped
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+    // set how many ticks and edits the history keeps, 0 turns it off
+    pub fn set_history_depth(&mut self, depth: u32) {
+        self.history.set_depth(depth as usize);
+    }
+    pub fn history_depth(&self) -> u32 {
+        self.history.depth() as u32
+    }
+    // keep only the changed cells of each tick instead of a full copy
+    pub fn set_history_compression(&mut self, compress: bool) {
+        self.history.set_compress(compress);
+    }
+    // go back to the generation before the last tick (or step), undoing
+    // any edits made since. Returns false when the history is empty.
+    // The history holds the grid, so on the unbounded backends the pattern
+    // outside of the window is lost
+    pub fn step_back(&mut self) -> bool {
+        match self.history.step_back(&mut self.cells) {
+            Some(generation) => {
+                self.generation = generation;
+                self.active.mark_all(self.width, self.height);
+                self.all_cells_replaced();
+                self.reload_backend();
+                true
             }
+            None => false,
+        }
+    }
+    // go back to an earlier generation still in the history
+    pub fn rewind_to(&mut self, generation: u64) -> Result<(), String> {
+        if generation > self.generation {
+            return Err(format!(
+                "generation {} is ahead of the current generation {}",
+                generation, self.generation
+            ));
+        }
+        match self.history.oldest_generation() {
+            Some(oldest) if oldest <= generation => {}
+            _ if generation == self.generation => {}
+            _ => return Err(format!("generation {} is no longer in the history", generation)),
+        }
+        while self.generation > generation {
+            self.step_back();
         }
+        // a HashLife step may have jumped over the generation
+        while self.generation < generation {
+            self.tick();
+        }
+        Ok(())
+    }
+    // undo the last toggle_cell, as long as no tick came after it
+    pub fn undo(&mut self) -> bool {
+        let undone = self.history.undo(&mut self.cells);
+        if let Some(idx) = undone {
+            self.sync_cell(idx as usize);
+        }
+        undone.is_some()
+    }
+    // redo the last undone toggle_cell
+    pub fn redo(&mut self) -> bool {
+        let redone = self.history.redo(&mut self.cells);
+        if let Some(idx) = redone {
+            self.sync_cell(idx as usize);
+        }
+        redone.is_some()
+    }
+    // work the cells out for the next generation
+    fn next_generation(&mut self) {
+        // the sparse backend steps the whole plane, the grid is only the
+        // window onto it
+        let whole = [(0, 0, self.height, self.width)];
+        if let Engine::Sparse(sparse) = &mut self.engine {
+            self.stats = sparse.tick(&self.rule);
+            let previous = self.cells.clone();
+            let (row, col) = self.viewport;
+            sparse.fill_window(row, col, self.width, self.height, &mut self.cells);
+            self.list_changes(&previous, &whole);
+            return;
+        }
+        // the packed backend ticks 64 cells at a time and then unpacks
+        // them so the byte per cell view stays valid for js
+        if let Engine::Packed(packed) = &mut self.engine {
+            if PackedGrid::supports(&self.rule, self.topology) {
+                packed.tick(&self.rule, self.topology);
+                let previous = self.cells.clone();
+                packed.unpack(&mut self.cells);
+                self.stats = TickStats::whole(self.width, self.height);
+                self.list_changes(&previous, &whole);
+                return;
+            }
+        }
+        // only the tiles near the ones that changed last tick can change
+        let range = self.rule.larger().map_or(1, |larger| larger.range());
+        let regions = self.active.regions(self.topology, range);
+        let grid = Grid {
+            cells: &self.cells,
+            width: self.width,
+            height: self.height,
+            topology: self.topology,
+        };
+        let next = grid.step_regions(&self.rule, &regions);
+        self.active.record(&regions, &self.cells, &next);
+        self.stats = self.active.stats(&regions);
         // Initialize the Universe structure with the current status
-        self.cells = next;
+        let previous = std::mem::replace(&mut self.cells, next);
+        // only the stepped regions can have changed
+        self.list_changes(&previous, &regions);
+        // the packed backend falls back on the dense tick for the
+        // rules and topologies it can't handle
+        if let Engine::Packed(_) = self.engine {
+            self.reload_backend();
+        }
     }
 
     // given the row and column find the
@@ -198,38 +673,22 @@ impl Universe {
         // idx = row * width + col
         (row * self.width + column) as usize
     }
-    // count the live neighbors around a given cell
-    // located at a given row col index
-    fn live_neighbor_count(&self, row: u32, column: u32) -> u8 {
-        // a mutable to hold the count
-        let mut count = 0;
-        // iterate using deltas
-        for delta_row in [self.height - 1, 0, 1].iter().cloned() {
-            for delta_col in [self.width - 1, 0, 1].iter().cloned() {
-                if delta_row == 0 && delta_col == 0 {
-                    continue
-                }
-                // use modulo to handle the univers edges
-                // in this case the neighbor of an edge cell will
-                // be the edge cell at the other side of the universe
-                let neighbor_row = (row + delta_row) % self.height;
-                let neighbor_col = (column + delta_col) % self.width;
-                // get the vector index of the neighbor row and col
-                let idx = self.get_index(neighbor_row, neighbor_col);
-                // update the count by getting the alive neighbor cells
-                // if alive: +=1 increase count
-                // if dead: +=0 do nothing
-                count += self.cells[idx] as u8;
-            }
-        }
-        count
-    }
     // function that updates the universe with the new toggled state
     pub fn toggle_cell(&mut self, row: u32, col: u32) {
         // get flat idx
         let idx = self.get_index(row, col);
         // toggle the cell
+        let old = self.cells[idx];
         self.cells[idx].toggle();
+        self.history.record_edit(idx as u32, old);
+        // and keep the backend in step
+        self.sync_cell(idx);
+    }
+}
+
+impl Default for Universe {
+    fn default() -> Universe {
+        Universe::new()
     }
 }
 
@@ -237,10 +696,129 @@ impl Universe {
 // the reason is rust wasm cant return references. So we do rust level 
 // testing of the functionality
 impl Universe {
+    // an all dead universe of the given size, which has to have been
+    // checked with `check_size` unless it is already in use
+    fn blank(width: u32, height: u32) -> Universe {
+        // Initialize a panick hook in the constructor
+        // so we can console log the panicks
+        utils::set_panic_hook();
+
+        Universe {
+            width,
+            height,
+            cells: vec![Cell::DEAD; (width * height) as usize],
+            rule: Rule::default(),
+            topology: Topology::default(),
+            engine: Engine::Dense,
+            generation: 0,
+            // the history is off until js asks for it, so ticking
+            // costs nothing extra
+            history: History::new(0, true),
+            viewport: (0, 0),
+            active: ActiveTiles::new(width, height),
+            stats: TickStats::default(),
+            changed: Vec::new(),
+            all_changed: true,
+            version: 0,
+            changed_since: 0,
+        }
+    }
+    // build a universe holding a pattern. A bounded grid on the pattern's
+    // rule ("B3/S23:T100,100") sizes the universe and the pattern is
+    // centered in it, as Golly does
+    pub fn from_pattern(pattern: &Pattern) -> Result<Universe, String> {
+        let (mut width, mut height) = (pattern.width.max(1), pattern.height.max(1));
+        let (mut row, mut col) = (0, 0);
+        let grid = pattern.rule.as_ref().and_then(|rule| rule.split_once(':'));
+        if let Some((_, grid)) = grid {
+            if let (_, Some((w, h))) = Topology::parse_grid(grid)? {
+                if w < pattern.width || h < pattern.height {
+                    return Err(format!(
+                        "a {}x{} pattern doesn't fit in a {}x{} grid",
+                        pattern.width, pattern.height, w, h
+                    ));
+                }
+                row = (h - pattern.height) / 2;
+                col = (w - pattern.width) / 2;
+                width = w;
+                height = h;
+            }
+        }
+        check_size(width, height)?;
+        let mut universe = Universe::blank(width, height);
+        if let Some(rule) = &pattern.rule {
+            universe.set_rule(rule)?;
+        }
+        universe.load_pattern_at(row, col, pattern)?;
+        Ok(universe)
+    }
+    // Place a pattern with its top left corner at row, col. The pattern's
+    // bounding box is cleared first so its dead cells are copied too.
+    pub fn load_pattern_at(&mut self, row: u32, col: u32, pattern: &Pattern) -> Result<(), String> {
+        if row as u64 + pattern.height as u64 > self.height as u64
+            || col as u64 + pattern.width as u64 > self.width as u64
+        {
+            return Err(format!(
+                "a {}x{} pattern doesn't fit at row {}, col {} of a {}x{} universe",
+                pattern.width, pattern.height, row, col, self.width, self.height
+            ));
+        }
+        for r in row..row + pattern.height {
+            for c in col..col + pattern.width {
+                let idx = self.get_index(r, c);
+                self.cells[idx] = Cell::DEAD;
+            }
+        }
+        for &(r, c, state) in &pattern.cells {
+            let idx = self.get_index(row + r, col + c);
+            self.cells[idx] = Cell::new(state);
+        }
+        self.cells_replaced();
+        Ok(())
+    }
+    // cut the live (and dying) cells out into a pattern, along with the
+    // rule and the bounded grid
+    pub fn to_pattern(&self) -> Pattern {
+        let (mut top, mut left, mut bottom, mut right) = (self.height, self.width, 0, 0);
+        for row in 0..self.height {
+            for col in 0..self.width {
+                if self.cells[self.get_index(row, col)] != Cell::DEAD {
+                    top = top.min(row);
+                    left = left.min(col);
+                    bottom = bottom.max(row + 1);
+                    right = right.max(col + 1);
+                }
+            }
+        }
+        let mut cells = Vec::new();
+        for row in top..bottom {
+            for col in left..right {
+                let cell = self.cells[self.get_index(row, col)];
+                if cell != Cell::DEAD {
+                    cells.push((row - top, col - left, cell.state()));
+                }
+            }
+        }
+        Pattern {
+            width: right.saturating_sub(left),
+            height: bottom.saturating_sub(top),
+            cells,
+            rule: Some(format!("{}:{}", self.rule, self.topology())),
+            ..Pattern::default()
+        }
+    }
     // get cells: returns a reference to a vector slice the cells
     pub fn get_cells(&self) -> &[Cell] {
         &self.cells
     }
+    // get the rule the universe evolves with
+    pub fn get_rule(&self) -> &Rule {
+        &self.rule
+    }
+    // get the topology of the universe
+    pub fn get_topology(&self) -> Topology {
+        self.topology
+    }
     // set cells to be alive by taking a list of row, col tuples
     pub fn set_cells(&mut self, cells: &[(u32, u32)]) {
         // iterate over the cells, get row, cols and set the corresponding
@@ -248,15 +826,230 @@ impl Universe {
         for (row, col) in cells.iter().cloned() {
             // get the flat index of the cell
             let idx = self.get_index(row, col);
-            self.cells[idx] = Cell::Alive;
+            self.cells[idx] = Cell::ALIVE;
         }
+        self.cells_replaced();
     }
+    // the cells of the region are drawn row by row, one number each
+    fn fill_random(&mut self, row: u32, col: u32, width: u32, height: u32, seed: u64, density: f64) {
+        let mut random = Random::new(seed);
+        for r in row..row + height {
+            for c in col..col + width {
+                let idx = self.get_index(r, c);
+                self.cells[idx] = if random.next_f64() < density {
+                    Cell::ALIVE
+                } else {
+                    Cell::DEAD
+                };
+            }
+        }
+        self.cells_replaced();
+    }
+    // switch to another rule, as long as the backend can run it
+    fn replace_rule(&mut self, rule: Rule) -> Result<(), String> {
+        if let Some(error) = unsupported(self.backend(), &rule) {
+            return Err(error.to_string());
+        }
+        match &mut self.engine {
+            Engine::HashLife(hashlife) => hashlife.set_rule(rule.clone()),
+            // the quiet tiles may not be quiet under the new rule
+            Engine::Sparse(sparse) => sparse.mark_all(),
+            _ => {}
+        }
+        self.rule = rule;
+        self.active.mark_all(self.width, self.height);
+        Ok(())
+    }
+    // the cells were replaced wholesale, the history no longer
+    // leads up to them
+    fn cells_replaced(&mut self) {
+        self.history.clear();
+        self.active.mark_all(self.width, self.height);
+        self.all_cells_replaced();
+        self.reload_backend();
+    }
+    // js has to redraw every cell
+    fn all_cells_replaced(&mut self) {
+        self.changed.clear();
+        self.all_changed = true;
+        self.version += 1;
+        self.changed_since = self.version;
+    }
+    // list the cells inside the regions which changed from before
+    fn list_changes(&mut self, before: &[Cell], regions: &[(u32, u32, u32, u32)]) {
+        self.changed.clear();
+        self.all_changed = false;
+        self.changed_since = self.version;
+        self.version += 1;
+        for &(top, left, bottom, right) in regions {
+            for row in top..bottom {
+                for col in left..right {
+                    let idx = self.get_index(row, col);
+                    if before[idx] != self.cells[idx] {
+                        self.changed.push(idx as u32);
+                    }
+                }
+            }
+        }
+        self.changed.sort_unstable();
+    }
+    // count the generations of a tick or step and record it
+    fn finish_step(&mut self, before: Option<Vec<Cell>>, generations: u64) {
+        if let Some(before) = before {
+            self.history.record_step(before, &self.cells, self.generation);
+        }
+        self.generation += generations;
+    }
+    // copy a single cell over to the backend
+    fn sync_cell(&mut self, idx: usize) {
+        let (row, col) = (idx as u32 / self.width, idx as u32 % self.width);
+        self.active.mark_cell(row, col);
+        self.changed.push(idx as u32);
+        self.version += 1;
+        let (top, left) = self.viewport;
+        let (plane_row, plane_col) = (top + row as i64, left + col as i64);
+        match &mut self.engine {
+            Engine::Dense => {}
+            Engine::HashLife(hashlife) => {
+                hashlife.set(plane_row, plane_col, self.cells[idx].state())
+            }
+            Engine::Packed(packed) => packed.set(row, col, self.cells[idx]),
+            Engine::Sparse(sparse) => sparse.set(plane_row, plane_col, self.cells[idx].state()),
+        }
+    }
+    // rebuild the backend from the cells after they are replaced wholesale
+    fn reload_backend(&mut self) {
+        self.engine = self.build_engine(self.backend());
+    }
+    fn build_engine(&self, backend: Backend) -> Engine {
+        match backend {
+            Backend::Dense => Engine::Dense,
+            Backend::HashLife => {
+                let mut hashlife =
+                    HashLife::from_cells(self.rule.clone(), &self.cells, self.width, self.height);
+                hashlife.shift(self.viewport.0, self.viewport.1);
+                Engine::HashLife(hashlife)
+            }
+            Backend::Packed => {
+                Engine::Packed(PackedGrid::from_cells(&self.cells, self.width, self.height))
+            }
+            Backend::Sparse => {
+                let (row, col) = self.viewport;
+                let sparse = SparseGrid::from_cells(&self.cells, self.width, self.height, row, col);
+                Engine::Sparse(sparse)
+            }
+        }
+    }
+
+}
+
+// make sure a width x height universe can be allocated
+fn check_size(width: u32, height: u32) -> Result<(), String> {
+    match width.checked_mul(height) {
+        Some(cells) if cells <= MAX_CELLS => Ok(()),
+        _ => Err(format!(
+            "a {}x{} universe is more than the {} cells allowed",
+            width, height, MAX_CELLS
+        )),
+    }
+}
 
+// why a backend can't run a rule, None when it can
+fn unsupported(backend: Backend, rule: &Rule) -> Option<&'static str> {
+    match backend {
+        Backend::HashLife if !HashLife::supports(rule) => {
+            Some("the HashLife backend can't run B0 or Larger than Life rules")
+        }
+        Backend::Sparse if !SparseGrid::supports(rule) => {
+            Some("the sparse backend can't run B0 rules")
+        }
+        _ => None,
+    }
 }
 
 // A Println! like macro using tocken trees (tt) to console log in js
+#[allow(unused_macros)]
 macro_rules! log {
     ($($t:tt)*) => {
-        web_sys::console::log_1(format!($(tt)*).into());
+        web_sys::console::log_1(&format!($($t)*).into());
     };
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the indices of the cells which differ between two snapshots
+    fn diff(before: &[Cell], after: &[Cell]) -> Vec<u32> {
+        (0..before.len()).filter(|&i| before[i] != after[i]).map(|i| i as u32).collect()
+    }
+
+    // a soup which keeps changing for a while, on the given backend
+    fn soup(backend: Backend) -> Universe {
+        let mut universe = Universe::new();
+        universe.set_width(64);
+        universe.set_height(48);
+        universe.set_rule("B3/S23:P").unwrap();
+        universe.randomize_region(8, 8, 32, 32, 5, 0.4).unwrap();
+        universe.set_backend(backend).unwrap();
+        universe
+    }
+
+    #[test]
+    fn lists_the_cells_each_tick_changes() {
+        for backend in [Backend::Dense, Backend::HashLife, Backend::Packed, Backend::Sparse] {
+            let mut universe = soup(backend);
+            assert!(universe.all_cells_changed());
+            for generation in 0..20 {
+                let (before, version) = (universe.get_cells().to_vec(), universe.version);
+                universe.tick();
+                assert!(!universe.all_cells_changed());
+                assert_eq!(universe.changed_since, version);
+                assert!(universe.version > version);
+                assert_eq!(
+                    universe.changed,
+                    diff(&before, universe.get_cells()),
+                    "{:?} at {}",
+                    backend,
+                    generation
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn lists_the_cells_a_step_changes() {
+        for backend in [Backend::Dense, Backend::HashLife, Backend::Sparse] {
+            let mut universe = soup(backend);
+            universe.tick();
+            let (before, version) = (universe.get_cells().to_vec(), universe.version);
+            universe.step(3).unwrap();
+            // the whole step since the version before it, not its last tick
+            assert_eq!(universe.changed_since, version, "{:?}", backend);
+            assert_eq!(universe.changed, diff(&before, universe.get_cells()), "{:?}", backend);
+        }
+    }
+
+    #[test]
+    fn toggles_go_on_the_end_of_the_list() {
+        let mut universe = soup(Backend::Dense);
+        universe.tick();
+        let (before, version) = (universe.get_cells().to_vec(), universe.version);
+        universe.tick();
+        universe.toggle_cell(0, 0);
+        universe.toggle_cell(47, 63);
+        // the tick's changes and the toggles after it, still counted
+        // from the version before the tick
+        assert_eq!(universe.changed_since, version);
+        assert!(universe.version > version + 1);
+        let mut listed = universe.changed.clone();
+        assert_eq!(listed[listed.len() - 2..], [0, 47 * 64 + 63]);
+        listed.sort_unstable();
+        listed.dedup();
+        assert_eq!(listed, diff(&before, universe.get_cells()));
+        // and replacing the cells leaves nothing to list
+        universe.set_width(32);
+        assert!(universe.all_cells_changed());
+        assert!(universe.changed.is_empty());
+        assert_eq!(universe.changed_since, universe.version);
+    }
+}
@@ -0,0 +1,452 @@
+use wasm_bindgen::prelude::*;
+
+use crate::{Renderer, Universe};
+
+// the largest image to_png will make, in pixels
+const MAX_PIXELS: u64 = 1 << 26;
+
+#[wasm_bindgen]
+impl Universe {
+    // A PNG snapshot of the board, each cell a cell_size square. The
+    // palette gives the r, g, b of each state like `palette` does, the
+    // states it leaves out (all of them when it is empty) take the
+    // universe's colours. Grid lines go between the cells when asked
+    // for. An empty crop takes in the grid, otherwise it is a [top, left,
+    // bottom, right] rectangle as `pattern_bounds` gives, so on the
+    // unbounded backends it can be anywhere on the plane.
+    pub fn to_png(
+        &self,
+        cell_size: u32,
+        palette: &[u8],
+        grid_lines: bool,
+        crop: &[i32],
+    ) -> Result<Vec<u8>, String> {
+        let (states, width, height) = match *crop {
+            [] => {
+                let states = self.cells.iter().map(|cell| cell.state()).collect();
+                (states, self.width, self.height)
+            }
+            [top, left, bottom, right] if bottom > top && right > left => {
+                let (width, height) = ((right - left) as u32, (bottom - top) as u32);
+                (self.viewport_cells(top, left, width, height), width, height)
+            }
+            _ => return Err("crop is [top, left, bottom, right] or empty".to_string()),
+        };
+        let lines = grid_lines as u64;
+        let stride = cell_size.max(1) as u64 + lines;
+        let pixels = (width as u64 * stride + lines) * (height as u64 * stride + lines);
+        if width == 0 || height == 0 || pixels > MAX_PIXELS {
+            let size = format!("{}x{}", width, height);
+            return Err(format!("a {} image at {} pixels a cell is too big", size, cell_size));
+        }
+        let mut renderer = Renderer::new(cell_size, grid_lines);
+        let rgba: Vec<u8> = palette
+            .chunks_exact(3)
+            .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 0xFF])
+            .collect();
+        renderer.set_colors(&rgba);
+        let colors = renderer.colors(self);
+        renderer.draw(&states, width, height, &colors);
+        Ok(encode(renderer.image_width(), renderer.image_height(), renderer.image()))
+    }
+}
+
+// the 8 byte signature every PNG starts with
+pub(crate) const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+// Encode RGBA8 pixels, row by row, as a PNG: the rows go unfiltered into
+// a single zlib compressed IDAT chunk.
+pub(crate) fn encode(width: u32, height: u32, rgba: &[u8]) -> Vec<u8> {
+    let mut png = SIGNATURE.to_vec();
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // 8 bits a channel, colour type 6 (rgba), deflate, no filter
+    // choice, no interlacing
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+    chunk(&mut png, b"IHDR", &header);
+    chunk(&mut png, b"IDAT", &zlib(&filter_rows(width as usize * 4, rgba)));
+    chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+// every row of pixels, row bytes long, after a 0 byte for filter type none
+pub(crate) fn filter_rows(row: usize, pixels: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(pixels.len() + pixels.len() / row.max(1));
+    for line in pixels.chunks(row.max(1)) {
+        data.push(0);
+        data.extend_from_slice(line);
+    }
+    data
+}
+
+// a chunk: its length, kind and data and the crc of the kind and data
+pub(crate) fn chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut table = [0u32; 256];
+    for (n, entry) in table.iter_mut().enumerate() {
+        let mut c = n as u32;
+        for _ in 0..8 {
+            c = if c & 1 == 1 { 0xEDB8_8320 ^ (c >> 1) } else { c >> 1 };
+        }
+        *entry = c;
+    }
+    let crc = bytes.iter().fold(0xFFFF_FFFF, |crc, &byte| {
+        table[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    });
+    crc ^ 0xFFFF_FFFF
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552 bytes at most before the sums have to be reduced
+    for block in bytes.chunks(5552) {
+        for &byte in block {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+// the base lengths and extra bits of the deflate length codes 257..=285
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115,
+    131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+// and of the distance codes 0..=29
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+// how far back a match may reach, and how many earlier places with the
+// same first three bytes are tried
+const WINDOW: usize = 1 << 15;
+const MAX_CHAIN: usize = 64;
+const MAX_MATCH: usize = 258;
+
+// Compress bytes into a zlib stream: a single deflate block with the
+// fixed Huffman codes, repeats found through a hash of the next three
+// bytes. Boards are mostly long runs of the same few colours, which this
+// squeezes well enough without building codes of its own.
+pub(crate) fn zlib(data: &[u8]) -> Vec<u8> {
+    let mut bits = BitWriter::new();
+    // deflate with a 32K window, no preset dictionary
+    bits.out.extend_from_slice(&[0x78, 0x9C]);
+    // the final block, fixed codes
+    bits.write(1, 1);
+    bits.write(1, 2);
+    let mut matcher = Matcher::new();
+    let mut i = 0;
+    while i < data.len() {
+        let (length, distance) = matcher.find(data, i);
+        if length >= 3 {
+            bits.length(length);
+            bits.distance(distance);
+            for k in i..i + length {
+                matcher.insert(data, k);
+            }
+            i += length;
+        } else {
+            bits.literal(data[i] as u16);
+            matcher.insert(data, i);
+            i += 1;
+        }
+    }
+    bits.literal(256);
+    let mut out = bits.finish();
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+// The places seen so far, chained by a hash of the three bytes there:
+// head holds the latest place for each hash and prev the one before each
+// place, for the last WINDOW places.
+struct Matcher {
+    head: Vec<usize>,
+    prev: Vec<usize>,
+}
+
+impl Matcher {
+    fn new() -> Matcher {
+        Matcher {
+            head: vec![usize::MAX; WINDOW],
+            prev: vec![usize::MAX; WINDOW],
+        }
+    }
+
+    fn hash(data: &[u8], i: usize) -> usize {
+        ((data[i] as usize) << 10 ^ (data[i + 1] as usize) << 5 ^ data[i + 2] as usize)
+            & (WINDOW - 1)
+    }
+
+    fn insert(&mut self, data: &[u8], i: usize) {
+        if i + 3 <= data.len() {
+            let hash = Matcher::hash(data, i);
+            self.prev[i % WINDOW] = self.head[hash];
+            self.head[hash] = i;
+        }
+    }
+
+    // the (length, distance) of the longest earlier match for the bytes
+    // at i, a length under 3 being no match
+    fn find(&self, data: &[u8], i: usize) -> (usize, usize) {
+        if i + 3 > data.len() {
+            return (0, 0);
+        }
+        let longest = (data.len() - i).min(MAX_MATCH);
+        let (mut best, mut distance) = (0, 0);
+        let mut candidate = self.head[Matcher::hash(data, i)];
+        // once a place is out of the window its slot in prev may have
+        // been reused, so the chain stops there
+        for _ in 0..MAX_CHAIN {
+            if candidate == usize::MAX || i - candidate > WINDOW {
+                break;
+            }
+            let length = (0..longest)
+                .take_while(|&k| data[candidate + k] == data[i + k])
+                .count();
+            if length > best {
+                best = length;
+                distance = i - candidate;
+                if length == longest {
+                    break;
+                }
+            }
+            candidate = self.prev[candidate % WINDOW];
+        }
+        (best, distance)
+    }
+}
+
+// writes bits from the least significant up, as deflate packs them
+struct BitWriter {
+    out: Vec<u8>,
+    bits: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn new() -> BitWriter {
+        BitWriter {
+            out: Vec::new(),
+            bits: 0,
+            count: 0,
+        }
+    }
+
+    fn write(&mut self, value: u32, count: u32) {
+        self.bits |= (value as u64) << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.out.push(self.bits as u8);
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    // Huffman codes go most significant bit first
+    fn code(&mut self, code: u32, length: u32) {
+        let reversed = code.reverse_bits() >> (32 - length);
+        self.write(reversed, length);
+    }
+
+    // a literal byte or the end of block (256) in the fixed codes
+    fn literal(&mut self, value: u16) {
+        let value = value as u32;
+        match value {
+            0..=143 => self.code(0x30 + value, 8),
+            144..=255 => self.code(0x190 + value - 144, 9),
+            256..=279 => self.code(value - 256, 7),
+            _ => self.code(0xC0 + value - 280, 8),
+        }
+    }
+
+    fn length(&mut self, length: usize) {
+        let code = LENGTH_BASE.iter().rposition(|&base| base as usize <= length).unwrap_or(0);
+        self.literal(257 + code as u16);
+        let extra = LENGTH_EXTRA[code] as u32;
+        self.write((length - LENGTH_BASE[code] as usize) as u32, extra);
+    }
+
+    fn distance(&mut self, distance: usize) {
+        let code = DISTANCE_BASE.iter().rposition(|&base| base as usize <= distance).unwrap_or(0);
+        self.code(code as u32, 5);
+        let extra = DISTANCE_EXTRA[code] as u32;
+        self.write((distance - DISTANCE_BASE[code] as usize) as u32, extra);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.bits as u8);
+        }
+        self.out
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::convert::TryInto;
+
+    use super::*;
+    use crate::Random;
+
+    // reads bits from the least significant up, as `BitWriter` writes them
+    struct BitReader<'a> {
+        data: &'a [u8],
+        at: usize,
+    }
+
+    impl BitReader<'_> {
+        fn bit(&mut self) -> u32 {
+            let bit = self.data[self.at / 8] >> (self.at % 8) & 1;
+            self.at += 1;
+            bit as u32
+        }
+
+        fn read(&mut self, count: u32) -> u32 {
+            (0..count).fold(0, |value, i| value | self.bit() << i)
+        }
+
+        // a Huffman code, most significant bit first
+        fn code(&mut self, length: u32) -> u32 {
+            (0..length).fold(0, |code, _| code << 1 | self.bit())
+        }
+
+        // a literal, length or the end of block in the fixed codes
+        fn symbol(&mut self) -> u32 {
+            let code = self.code(7);
+            if code <= 0x17 {
+                return 256 + code;
+            }
+            let code = code << 1 | self.bit();
+            match code {
+                0x30..=0xBF => code - 0x30,
+                0xC0..=0xC7 => 280 + code - 0xC0,
+                _ => 144 + (code << 1 | self.bit()) - 0x190,
+            }
+        }
+    }
+
+    // Undo `zlib`, which only writes fixed code blocks, checking the
+    // header and the Adler-32 on the way
+    pub(crate) fn inflate(stream: &[u8]) -> Vec<u8> {
+        assert_eq!(stream[0] & 0x0F, 8, "not deflate");
+        assert_eq!(u16::from_be_bytes([stream[0], stream[1]]) % 31, 0, "bad header check");
+        let mut bits = BitReader { data: &stream[2..], at: 0 };
+        let mut out: Vec<u8> = Vec::new();
+        loop {
+            let last = bits.read(1) == 1;
+            assert_eq!(bits.read(2), 1, "not a fixed code block");
+            loop {
+                let symbol = bits.symbol();
+                match symbol {
+                    0..=255 => out.push(symbol as u8),
+                    256 => break,
+                    _ => {
+                        let code = (symbol - 257) as usize;
+                        let length =
+                            LENGTH_BASE[code] as u32 + bits.read(LENGTH_EXTRA[code] as u32);
+                        let code = bits.code(5) as usize;
+                        let distance =
+                            DISTANCE_BASE[code] as u32 + bits.read(DISTANCE_EXTRA[code] as u32);
+                        let start = out.len() - distance as usize;
+                        for k in 0..length as usize {
+                            out.push(out[start + k]);
+                        }
+                    }
+                }
+            }
+            if last {
+                break;
+            }
+        }
+        let end = 2 + bits.at.div_ceil(8);
+        assert_eq!(stream.len(), end + 4, "the stream runs on past its end");
+        assert_eq!(stream[end..], adler32(&out).to_be_bytes());
+        out
+    }
+
+    // the (kind, data) of each chunk after the signature, checking their crcs
+    pub(crate) fn chunks(png: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+        assert_eq!(&png[..8], SIGNATURE);
+        let mut chunks = Vec::new();
+        let mut at = 8;
+        while at < png.len() {
+            let number = |at: usize| u32::from_be_bytes(png[at..at + 4].try_into().unwrap());
+            let length = number(at) as usize;
+            let body = &png[at + 4..at + 8 + length];
+            let crc = number(at + 8 + length);
+            assert_eq!(crc32(body), crc, "{}", String::from_utf8_lossy(&body[..4]));
+            chunks.push((body[..4].try_into().unwrap(), body[4..].to_vec()));
+            at += 12 + length;
+        }
+        chunks
+    }
+
+    #[test]
+    fn checksums_match_the_known_values() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+        // past the 5552 bytes the sums are reduced after
+        assert_eq!(adler32(&[0xFF; 6000]), 0xA497_59EA);
+    }
+
+    #[test]
+    fn zlib_inflates_back() {
+        let mut random = Random::new(7);
+        let noise: Vec<u8> = (0..5000).map(|_| random.next_u64() as u8).collect();
+        // runs longer than a match, repeats from further back than the
+        // window, every byte value and nothing at all
+        let mut repeats = Vec::new();
+        for i in 0..40_000u32 {
+            repeats.push((i / 1000 % 7) as u8);
+        }
+        repeats.extend_from_slice(&noise[..100]);
+        repeats.extend_from_within(..3000);
+        let every: Vec<u8> = (0..=255).collect();
+        for data in [&noise, &repeats, &every, &vec![0; 1000], &Vec::new()] {
+            assert!(&inflate(&zlib(data)) == data, "{} bytes", data.len());
+        }
+        // the runs squeeze down to a fraction
+        assert!(zlib(&[0; 100_000]).len() < 1000);
+    }
+
+    #[test]
+    fn encodes_a_small_frame() {
+        // a 2x2 image, red, green on the first row, blue, clear below
+        let rgba = [255, 0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 255, 0, 0, 0, 0];
+        let png = encode(2, 2, &rgba);
+        let chunks = chunks(&png);
+        let kinds: Vec<&[u8; 4]> = chunks.iter().map(|(kind, _)| kind).collect();
+        assert_eq!(kinds, [b"IHDR", b"IDAT", b"IEND"]);
+        assert_eq!(chunks[0].1, [0, 0, 0, 2, 0, 0, 0, 2, 8, 6, 0, 0, 0]);
+        let mut rows = vec![0];
+        rows.extend_from_slice(&rgba[..8]);
+        rows.push(0);
+        rows.extend_from_slice(&rgba[8..]);
+        assert_eq!(inflate(&chunks[1].1), rows);
+        assert!(chunks[2].1.is_empty());
+    }
+}
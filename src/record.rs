@@ -0,0 +1,227 @@
+use wasm_bindgen::prelude::*;
+
+use crate::png::{chunk, filter_rows, zlib, SIGNATURE};
+use crate::{gif, Universe};
+
+// the most pixels a recording may hold over all of its frames
+const MAX_PIXELS: u64 = 1 << 28;
+
+// The file a recording is written as
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RecordingFormat {
+    Gif = 0,
+    Apng = 1,
+}
+
+// Records a run as an animated GIF or APNG. `capture` is called after
+// each tick and keeps every (skip + 1)th board; `finish` then writes the
+// animation, each cell a cell_size square in the colours of the palette
+// (or the universe's), showing each frame for delay milliseconds. Both
+// formats store the frames as indices into the palette, one per state.
+#[wasm_bindgen]
+pub struct Recorder {
+    format: RecordingFormat,
+    cell_size: u32,
+    delay: u32,
+    skip: u32,
+    // r, g, b of the states, the universe's palette fills in the rest
+    palette: Vec<u8>,
+    // the palette and grid size of the first frame, which the others
+    // have to keep to
+    colors: Vec<u8>,
+    size: (u32, u32),
+    // the states of the cells of each frame kept
+    frames: Vec<Vec<u8>>,
+    // the number of boards offered to capture
+    offered: u64,
+}
+
+#[wasm_bindgen]
+impl Recorder {
+    pub fn new(format: RecordingFormat, cell_size: u32, delay: u32) -> Recorder {
+        Recorder {
+            format,
+            cell_size: cell_size.max(1),
+            delay,
+            skip: 0,
+            palette: Vec::new(),
+            colors: Vec::new(),
+            size: (0, 0),
+            frames: Vec::new(),
+            offered: 0,
+        }
+    }
+    // only keep every (skip + 1)th board offered, so a slow pattern can
+    // be shown faster
+    pub fn set_skip(&mut self, skip: u32) {
+        self.skip = skip;
+    }
+    // the r, g, b of each state from 0 up, as `Universe::palette` gives
+    pub fn set_palette(&mut self, palette: &[u8]) {
+        self.palette = palette[..palette.len() / 3 * 3].to_vec();
+    }
+    // the number of frames kept so far
+    pub fn frames(&self) -> u32 {
+        self.frames.len() as u32
+    }
+    // forget the frames kept so far
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.offered = 0;
+    }
+    // offer the board as it is now, it is kept unless skipped
+    pub fn capture(&mut self, universe: &Universe) -> Result<(), String> {
+        let offered = self.offered;
+        self.offered += 1;
+        if !offered.is_multiple_of(self.skip as u64 + 1) {
+            return Ok(());
+        }
+        let size = (universe.width, universe.height);
+        if self.frames.is_empty() {
+            let (width, height) = self.image_size(size);
+            if width > u16::MAX as u64 || height > u16::MAX as u64 {
+                return Err(format!("a {}x{} frame is too big", width, height));
+            }
+            self.size = size;
+            self.colors = self.palette.clone();
+            let palette = universe.palette();
+            if palette.len() > self.colors.len() {
+                self.colors.extend_from_slice(&palette[self.colors.len()..]);
+            }
+        } else if size != self.size {
+            return Err(format!(
+                "the universe is {}x{} but the recording is {}x{}",
+                size.0, size.1, self.size.0, self.size.1
+            ));
+        }
+        let (width, height) = self.image_size(size);
+        if (self.frames.len() as u64 + 1) * width * height > MAX_PIXELS {
+            return Err("the recording is too long to hold".to_string());
+        }
+        self.frames.push(universe.cells.iter().map(|cell| cell.state()).collect());
+        Ok(())
+    }
+    // write the frames kept as an animation
+    pub fn finish(&self) -> Result<Vec<u8>, String> {
+        if self.frames.is_empty() {
+            return Err("nothing has been recorded".to_string());
+        }
+        let (width, height) = self.image_size(self.size);
+        let frames: Vec<Vec<u8>> = self.frames.iter().map(|frame| self.scale(frame)).collect();
+        Ok(match self.format {
+            RecordingFormat::Gif => {
+                // in hundredths of a second
+                let delay = ((self.delay + 5) / 10).min(u16::MAX as u32) as u16;
+                gif::encode(width as u16, height as u16, &self.colors, &frames, delay)
+            }
+            RecordingFormat::Apng => self.apng(width as u32, height as u32, &frames),
+        })
+    }
+}
+
+impl Recorder {
+    // the size of a frame in pixels
+    fn image_size(&self, (width, height): (u32, u32)) -> (u64, u64) {
+        (width as u64 * self.cell_size as u64, height as u64 * self.cell_size as u64)
+    }
+
+    // each state of a frame blown up to a cell_size square
+    fn scale(&self, frame: &[u8]) -> Vec<u8> {
+        let size = self.cell_size as usize;
+        let width = self.size.0 as usize;
+        // a state past the end of the palette takes its last colour
+        let last = (self.colors.len() / 3).clamp(1, 256) as u8 - 1;
+        let mut pixels = Vec::with_capacity(frame.len() * size * size);
+        for line in frame.chunks(width.max(1)) {
+            let row: Vec<u8> = line
+                .iter()
+                .flat_map(|&state| std::iter::repeat_n(state.min(last), size))
+                .collect();
+            for _ in 0..size {
+                pixels.extend_from_slice(&row);
+            }
+        }
+        pixels
+    }
+
+    // An APNG of palette indices: the first frame is the PNG's image,
+    // the rest follow in fdAT chunks, each after an fcTL giving its delay.
+    fn apng(&self, width: u32, height: u32, frames: &[Vec<u8>]) -> Vec<u8> {
+        let mut png = SIGNATURE.to_vec();
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&width.to_be_bytes());
+        header.extend_from_slice(&height.to_be_bytes());
+        // 8 bits an index, colour type 3 (palette)
+        header.extend_from_slice(&[8, 3, 0, 0, 0]);
+        chunk(&mut png, b"IHDR", &header);
+        // the number of frames, looping forever
+        let mut control = (frames.len() as u32).to_be_bytes().to_vec();
+        control.extend_from_slice(&0u32.to_be_bytes());
+        chunk(&mut png, b"acTL", &control);
+        chunk(&mut png, b"PLTE", &self.colors[..self.colors.len().min(256 * 3)]);
+        // the fcTL and fdAT chunks share one sequence
+        let mut sequence = 0u32;
+        let delay = self.delay.min(u16::MAX as u32) as u16;
+        for (n, frame) in frames.iter().enumerate() {
+            let mut frame_control = sequence.to_be_bytes().to_vec();
+            frame_control.extend_from_slice(&width.to_be_bytes());
+            frame_control.extend_from_slice(&height.to_be_bytes());
+            // at the top left, shown for delay / 1000 s, left in place
+            // and drawn over what was there
+            frame_control.extend_from_slice(&[0; 8]);
+            frame_control.extend_from_slice(&delay.to_be_bytes());
+            frame_control.extend_from_slice(&1000u16.to_be_bytes());
+            frame_control.extend_from_slice(&[0, 0]);
+            chunk(&mut png, b"fcTL", &frame_control);
+            sequence += 1;
+            let data = zlib(&filter_rows(width as usize, frame));
+            if n == 0 {
+                chunk(&mut png, b"IDAT", &data);
+            } else {
+                let mut frame_data = sequence.to_be_bytes().to_vec();
+                frame_data.extend_from_slice(&data);
+                chunk(&mut png, b"fdAT", &frame_data);
+                sequence += 1;
+            }
+        }
+        chunk(&mut png, b"IEND", &[]);
+        png
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    use super::*;
+    use crate::png::tests::{chunks, inflate};
+
+    #[test]
+    fn apng_holds_every_frame() {
+        let blinker = "x = 3, y = 3, rule = B3/S23:P3,3\nbo$bo$bo!";
+        let mut universe = Universe::from_rle(blinker).unwrap();
+        let mut recorder = Recorder::new(RecordingFormat::Apng, 2, 100);
+        recorder.set_palette(&[0, 0, 0, 255, 255, 255]);
+        recorder.capture(&universe).unwrap();
+        universe.tick();
+        recorder.capture(&universe).unwrap();
+        let chunks = chunks(&recorder.finish().unwrap());
+        let kinds: Vec<&[u8; 4]> = chunks.iter().map(|(kind, _)| kind).collect();
+        assert_eq!(kinds, [b"IHDR", b"acTL", b"PLTE", b"fcTL", b"IDAT", b"fcTL", b"fdAT", b"IEND"]);
+        // a 6x6 image of palette indices, two frames played forever
+        assert_eq!(chunks[0].1, [0, 0, 0, 6, 0, 0, 0, 6, 8, 3, 0, 0, 0]);
+        assert_eq!(chunks[1].1, [0, 0, 0, 2, 0, 0, 0, 0]);
+        assert_eq!(chunks[2].1, [0, 0, 0, 255, 255, 255]);
+        let number = |data: &[u8]| u32::from_be_bytes(data[..4].try_into().unwrap());
+        // the frame controls and the frame data share a sequence
+        assert_eq!(number(&chunks[3].1), 0);
+        assert_eq!(number(&chunks[5].1), 1);
+        assert_eq!(number(&chunks[6].1), 2);
+        // each cell is a 2x2 square after the filter byte of every row
+        let vertical = [0, 0, 0, 1, 1, 0, 0];
+        let (dead, live) = ([0; 7], [0, 1, 1, 1, 1, 1, 1]);
+        assert_eq!(inflate(&chunks[4].1), [vertical; 6].concat());
+        assert_eq!(inflate(&chunks[6].1[4..]), [dead, dead, live, live, dead, dead].concat());
+    }
+}
@@ -0,0 +1,216 @@
+use wasm_bindgen::prelude::*;
+
+use crate::{Cell, Universe};
+
+// the colour of the lines between the cells unless set, as index.js had it
+const GRID_COLOR: [u8; 4] = [0xCC, 0xCC, 0xCC, 0xFF];
+
+// Draws the universe into an RGBA8 image, four bytes a pixel row by row,
+// which js can put on a canvas in one putImageData instead of a fillRect
+// per cell. Each cell is a cell_size square, with a line of one pixel
+// between them and around the edge when grid lines are on. The image
+// covers a (row, col, width, height) rectangle of the grid, the whole of
+// it unless a viewport is set; cells past the edge of the grid are drawn
+// dead.
+#[wasm_bindgen]
+pub struct Renderer {
+    cell_size: u32,
+    grid_lines: bool,
+    grid_color: [u8; 4],
+    // the colour of each state, the universe's palette fills in the
+    // states left out
+    colors: Vec<[u8; 4]>,
+    viewport: Option<(i32, i32, u32, u32)>,
+    pixels: Vec<u8>,
+    image_width: u32,
+    image_height: u32,
+    // what the image shows, None when it has to be drawn afresh
+    drawn: Option<Drawn>,
+}
+
+// what an image was drawn from: the universe's version, the size of its
+// grid, the rectangle of it shown and the colours of the states
+struct Drawn {
+    version: u64,
+    grid: (u32, u32),
+    rect: (i32, i32, u32, u32),
+    colors: Vec<[u8; 4]>,
+}
+
+#[wasm_bindgen]
+impl Renderer {
+    pub fn new(cell_size: u32, grid_lines: bool) -> Renderer {
+        Renderer {
+            cell_size: cell_size.max(1),
+            grid_lines,
+            grid_color: GRID_COLOR,
+            colors: Vec::new(),
+            viewport: None,
+            pixels: Vec::new(),
+            image_width: 0,
+            image_height: 0,
+            drawn: None,
+        }
+    }
+    pub fn set_cell_size(&mut self, cell_size: u32) {
+        self.cell_size = cell_size.max(1);
+        self.drawn = None;
+    }
+    pub fn set_grid_lines(&mut self, grid_lines: bool) {
+        self.grid_lines = grid_lines;
+        self.drawn = None;
+    }
+    pub fn set_grid_color(&mut self, r: u8, g: u8, b: u8, a: u8) {
+        self.grid_color = [r, g, b, a];
+        self.drawn = None;
+    }
+    // the colours of the states from 0 up as r, g, b, a bytes; an empty
+    // array goes back to the universe's palette
+    pub fn set_colors(&mut self, colors: &[u8]) {
+        self.colors = colors
+            .chunks_exact(4)
+            .map(|c| [c[0], c[1], c[2], c[3]])
+            .collect();
+        self.drawn = None;
+    }
+    // only draw the width x height cells with their top left corner at
+    // row, col of the grid
+    pub fn set_viewport(&mut self, row: i32, col: i32, width: u32, height: u32) {
+        self.viewport = Some((row, col, width, height));
+        self.drawn = None;
+    }
+    // go back to drawing the whole grid
+    pub fn clear_viewport(&mut self) {
+        self.viewport = None;
+        self.drawn = None;
+    }
+    // draw every cell afresh
+    pub fn render(&mut self, universe: &Universe) {
+        let drawn = self.drawing(universe);
+        let (top, left, width, height) = drawn.rect;
+        let states: Vec<u8> = (0..height as i32)
+            .flat_map(|r| (0..width as i32).map(move |c| state_at(universe, top + r, left + c)))
+            .collect();
+        self.draw(&states, width, height, &drawn.colors);
+        self.drawn = Some(drawn);
+    }
+    // Bring the image up to date, redrawing only the cells the universe
+    // lists as changed when nothing has been missed since the last draw
+    // and everything else is as it was; otherwise draw every cell.
+    pub fn update(&mut self, universe: &Universe) {
+        let drawing = self.drawing(universe);
+        let up_to_date = match &self.drawn {
+            Some(drawn) => {
+                drawn.version >= universe.changed_since
+                    && drawn.grid == drawing.grid
+                    && drawn.rect == drawing.rect
+                    && drawn.colors == drawing.colors
+            }
+            None => false,
+        };
+        if !up_to_date {
+            self.render(universe);
+            return;
+        }
+        let (top, left, width, height) = drawing.rect;
+        for &idx in &universe.changed {
+            let row = (idx / universe.width) as i32 - top;
+            let col = (idx % universe.width) as i32 - left;
+            if (0..height as i32).contains(&row) && (0..width as i32).contains(&col) {
+                let state = universe.cells[idx as usize].state();
+                self.fill_cell(row as u32, col as u32, color(&drawing.colors, state));
+            }
+        }
+        self.drawn = Some(drawing);
+    }
+    // Pointer to the image's pixels, image_width * image_height * 4 bytes
+    // of r, g, b, a. Overlay a Uint8ClampedArray of `pixels_len` bytes to
+    // make an ImageData from.
+    pub fn pixels(&self) -> *const u8 {
+        self.pixels.as_ptr()
+    }
+    pub fn pixels_len(&self) -> u32 {
+        self.pixels.len() as u32
+    }
+    // the size of the image in pixels, as of the last draw
+    pub fn image_width(&self) -> u32 {
+        self.image_width
+    }
+    pub fn image_height(&self) -> u32 {
+        self.image_height
+    }
+}
+
+impl Renderer {
+    // the image's pixels as r, g, b, a bytes
+    pub fn image(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    // draw a width x height block of states, row by row, afresh
+    pub(crate) fn draw(&mut self, states: &[u8], width: u32, height: u32, colors: &[[u8; 4]]) {
+        let stride = self.cell_size + self.grid_lines as u32;
+        self.image_width = width * stride + self.grid_lines as u32;
+        self.image_height = height * stride + self.grid_lines as u32;
+        let background = if self.grid_lines { self.grid_color } else { color(colors, 0) };
+        let len = self.image_width as usize * self.image_height as usize;
+        self.pixels.resize(len * 4, 0);
+        for pixel in self.pixels.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&background);
+        }
+        for (idx, &state) in states.iter().enumerate() {
+            let (r, c) = (idx as u32 / width, idx as u32 % width);
+            self.fill_cell(r, c, color(colors, state));
+        }
+        self.drawn = None;
+    }
+
+    // the colour of each state, the ones set first and then the
+    // universe's palette
+    pub(crate) fn colors(&self, universe: &Universe) -> Vec<[u8; 4]> {
+        let palette = universe.palette();
+        let mut colors = self.colors.clone();
+        for rgb in palette.chunks_exact(3).skip(colors.len()) {
+            colors.push([rgb[0], rgb[1], rgb[2], 0xFF]);
+        }
+        colors
+    }
+
+    // what drawing the universe now would be drawn from
+    fn drawing(&self, universe: &Universe) -> Drawn {
+        Drawn {
+            version: universe.version,
+            grid: (universe.width, universe.height),
+            rect: self.viewport.unwrap_or((0, 0, universe.width, universe.height)),
+            colors: self.colors(universe),
+        }
+    }
+
+    // fill the square of the cell at row, col of the image
+    fn fill_cell(&mut self, row: u32, col: u32, color: [u8; 4]) {
+        let lines = self.grid_lines as u32;
+        let stride = self.cell_size + lines;
+        let (x, y) = (col * stride + lines, row * stride + lines);
+        for py in y..y + self.cell_size {
+            let start = (py * self.image_width + x) as usize * 4;
+            let end = start + self.cell_size as usize * 4;
+            for pixel in self.pixels[start..end].chunks_exact_mut(4) {
+                pixel.copy_from_slice(&color);
+            }
+        }
+    }
+}
+
+// the state of the cell at row, col of the grid, dead past its edges
+fn state_at(universe: &Universe, row: i32, col: i32) -> u8 {
+    let (height, width) = (universe.height as i32, universe.width as i32);
+    if !(0..height).contains(&row) || !(0..width).contains(&col) {
+        return Cell::DEAD.state();
+    }
+    universe.cells[(row as u32 * universe.width + col as u32) as usize].state()
+}
+
+// the colour of a state, black for states past the end of the palette
+fn color(colors: &[[u8; 4]], state: u8) -> [u8; 4] {
+    colors.get(state as usize).copied().unwrap_or([0, 0, 0, 0xFF])
+}
@@ -0,0 +1,479 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::Cell;
+
+mod hensel;
+mod larger;
+mod table;
+
+pub use larger::{Larger, LargerShape};
+pub use table::RuleTable;
+
+// The neighbourhood a rule counts its live neighbours in.
+// Golly marks the non Moore ones with a suffix on the rulestring,
+// "V" for von Neumann and "H" for hexagonal.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Neighborhood {
+    // the 8 surrounding cells
+    Moore,
+    // the 4 orthogonal cells
+    VonNeumann,
+    // a hex grid mapped onto the square grid; the 3x3 block
+    // without the north east and south west corners
+    Hexagonal,
+}
+
+impl Neighborhood {
+    // the (delta_row, delta_col) offsets of the neighbours
+    pub fn offsets(&self) -> &'static [(i32, i32)] {
+        match self {
+            Neighborhood::Moore => &[
+                (-1, -1), (-1, 0), (-1, 1),
+                (0, -1), (0, 1),
+                (1, -1), (1, 0), (1, 1),
+            ],
+            Neighborhood::VonNeumann => &[(-1, 0), (0, -1), (0, 1), (1, 0)],
+            Neighborhood::Hexagonal => &[
+                (-1, -1), (-1, 0),
+                (0, -1), (0, 1),
+                (1, 0), (1, 1),
+            ],
+        }
+    }
+
+    // the largest neighbour count a cell can have
+    pub fn max_count(&self) -> usize {
+        self.offsets().len()
+    }
+
+    fn suffix(&self) -> &'static str {
+        match self {
+            Neighborhood::Moore => "",
+            Neighborhood::VonNeumann => "V",
+            Neighborhood::Hexagonal => "H",
+        }
+    }
+}
+
+// A Life-like rule; a dead cell is born when its live neighbour count
+// is in `birth` and a live cell stays alive when the count is in `survival`.
+// Conway's game of life is B3/S23.
+// Generations rules ("B2/S/C3", Brian's Brain) have more than two states;
+// a live cell that doesn't survive goes through the dying states 2, 3, ..
+// one a tick before it is dead, and only live cells count as neighbours.
+// Isotropic non-totalistic rules ("B2-a/S12") also look at where the live
+// neighbours are, see `hensel`, and Larger than Life rules
+// ("R5,C0,M1,S34..58,B34..45,NM") count further out, see `larger`.
+// Rule tables (Wireworld, JvN29) list their transitions state by state,
+// see `table`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rule {
+    // for isotropic rules, the counts with at least one arrangement
+    birth: [bool; 9],
+    survival: [bool; 9],
+    neighborhood: Neighborhood,
+    // the number of states including dead and alive, 2 for Life-like rules
+    states: u8,
+    // the arrangements of an isotropic non-totalistic rule, None when
+    // the counts are all there is to the rule
+    isotropic: Option<Box<Arrangements>>,
+    // the range, neighbourhood and counts of a Larger than Life rule,
+    // which stand in for the ones above
+    larger: Option<Box<Larger>>,
+    // the transitions of a rule table, which stand in for all of the above
+    table: Option<Box<RuleTable>>,
+}
+
+// the birth and survival of each of the 256 arrangements of the Moore
+// neighbours, indexed as for `Rule::next_cell_around`
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Arrangements {
+    birth: [bool; 256],
+    survival: [bool; 256],
+}
+
+impl Rule {
+    // the classic B3/S23 rule
+    pub fn conway() -> Rule {
+        let mut birth = [false; 9];
+        let mut survival = [false; 9];
+        birth[3] = true;
+        survival[2] = true;
+        survival[3] = true;
+        Rule {
+            birth,
+            survival,
+            neighborhood: Neighborhood::Moore,
+            states: 2,
+            isotropic: None,
+            larger: None,
+            table: None,
+        }
+    }
+
+    // read a rule from the text of a Golly .rule file with a @TABLE
+    pub fn from_table(text: &str) -> Result<Rule, String> {
+        let table = table::parse(text)?;
+        Ok(Rule {
+            birth: [false; 9],
+            survival: [false; 9],
+            neighborhood: Neighborhood::Moore,
+            states: table.states(),
+            isotropic: None,
+            larger: None,
+            table: Some(Box::new(table)),
+        })
+    }
+
+    pub fn neighborhood(&self) -> Neighborhood {
+        self.neighborhood
+    }
+
+    // the neighbour counts that bring a dead cell alive
+    pub fn birth(&self) -> &[bool; 9] {
+        &self.birth
+    }
+
+    // the neighbour counts that keep a live cell alive
+    pub fn survival(&self) -> &[bool; 9] {
+        &self.survival
+    }
+
+    // the number of states, more than 2 for a Generations rule
+    pub fn states(&self) -> u8 {
+        self.states
+    }
+
+    // does the rule only look at how many neighbours are alive
+    pub fn is_totalistic(&self) -> bool {
+        self.isotropic.is_none()
+    }
+
+    // does a dead cell with no live neighbours come alive
+    pub fn has_b0(&self) -> bool {
+        if let Some(table) = &self.table {
+            return table.has_b0();
+        }
+        match &self.larger {
+            Some(larger) => larger.born(0),
+            None => self.birth[0],
+        }
+    }
+
+    // the Larger than Life part of the rule, None for the rules on the
+    // 3x3 block
+    pub fn larger(&self) -> Option<&Larger> {
+        self.larger.as_deref()
+    }
+
+    // the rule table behind the rule, None for rulestrings
+    pub fn table(&self) -> Option<&RuleTable> {
+        self.table.as_deref()
+    }
+
+    // find the next state of a cell given the number of its live
+    // neighbours, for totalistic rules
+    pub fn next_cell(&self, cell: Cell, neighbors_alive: u8) -> Cell {
+        let count = neighbors_alive as usize;
+        self.next_state(cell, self.birth[count], self.survival[count])
+    }
+
+    // find the next state of a cell given which of its neighbours are
+    // alive, bit i being the neighbour at `neighborhood().offsets()[i]`
+    pub fn next_cell_around(&self, cell: Cell, neighbors: u8) -> Cell {
+        match &self.isotropic {
+            Some(arrangements) => {
+                let idx = neighbors as usize;
+                self.next_state(cell, arrangements.birth[idx], arrangements.survival[idx])
+            }
+            None => self.next_cell(cell, neighbors.count_ones() as u8),
+        }
+    }
+
+    // find the next state of a cell given the weighted count of the live
+    // cells in its neighbourhood, for Larger than Life rules
+    pub fn next_cell_weighted(&self, cell: Cell, count: u32) -> Cell {
+        match &self.larger {
+            Some(larger) => self.next_state(cell, larger.born(count), larger.survives(count)),
+            None => self.next_cell(cell, count.min(8) as u8),
+        }
+    }
+
+    fn next_state(&self, cell: Cell, born: bool, survives: bool) -> Cell {
+        match cell.state() {
+            0 if born => Cell::ALIVE,
+            0 => Cell::DEAD,
+            1 if survives => Cell::ALIVE,
+            // a dying cell carries on dying whatever its neighbours do
+            state if state + 1 < self.states => Cell::new(state + 1),
+            _ => Cell::DEAD,
+        }
+    }
+}
+
+impl Default for Rule {
+    fn default() -> Rule {
+        Rule::conway()
+    }
+}
+
+// one half of a rulestring; the counts and, when Hensel letters were
+// used, the arrangements
+type Half = ([bool; 9], Option<[bool; 256]>);
+
+// parse the digits of one half of a rulestring into a count table, each
+// digit may be followed by Hensel letters ("3aik") or by letters to
+// leave out ("2-a")
+fn parse_half(text: &str, neighborhood: Neighborhood) -> Result<Half, String> {
+    let mut counts = [false; 9];
+    let mut arrangements = [false; 256];
+    let mut isotropic = false;
+    // the digit being read, whether its letters are left out and its letters
+    let mut current: Option<(usize, bool, String)> = None;
+    let mut finish = |current: Option<(usize, bool, String)>| -> Result<(), String> {
+        let (count, negated, letters) = match current {
+            Some(current) => current,
+            None => return Ok(()),
+        };
+        if negated && letters.is_empty() {
+            return Err(format!("'{}-' needs the letters to leave out", count));
+        }
+        for letter in letters.chars() {
+            if !hensel::letters(count).contains(letter) {
+                return Err(format!("'{}{}' is not an arrangement of neighbours", count, letter));
+            }
+        }
+        for neighbors in 0..=255u8 {
+            if neighbors.count_ones() as usize != count {
+                continue;
+            }
+            let listed = hensel::letter(neighbors).is_some_and(|l| letters.contains(l));
+            if letters.is_empty() || listed != negated {
+                arrangements[neighbors as usize] = true;
+                counts[count] = true;
+            }
+        }
+        Ok(())
+    };
+    for c in text.chars() {
+        if let Some(digit) = c.to_digit(10) {
+            let count = digit as usize;
+            if count > neighborhood.max_count() {
+                return Err(format!(
+                    "neighbour count {} is out of range for this neighbourhood",
+                    count
+                ));
+            }
+            finish(current.take())?;
+            current = Some((count, false, String::new()));
+            continue;
+        }
+        if neighborhood != Neighborhood::Moore {
+            return Err(format!("unexpected character '{}' in rule", c));
+        }
+        isotropic = true;
+        match (c, current.as_mut()) {
+            ('-', Some((_, negated, letters))) if !*negated && letters.is_empty() => *negated = true,
+            (c, Some((_, _, letters))) if hensel::is_letter(c) && !letters.contains(c) => {
+                letters.push(c)
+            }
+            _ => return Err(format!("unexpected character '{}' in rule", c)),
+        }
+    }
+    finish(current.take())?;
+    Ok((counts, if isotropic { Some(arrangements) } else { None }))
+}
+
+// the arrangements of a half which only has counts
+fn every_arrangement(counts: &[bool; 9]) -> [bool; 256] {
+    let mut arrangements = [false; 256];
+    for (neighbors, arrangement) in arrangements.iter_mut().enumerate() {
+        *arrangement = counts[neighbors.count_ones() as usize];
+    }
+    arrangements
+}
+
+// write the counts of one half, along with the Hensel letters of an
+// isotropic rule; the letters taken or the ones left out, whichever
+// is shorter
+fn write_half(
+    f: &mut fmt::Formatter,
+    counts: &[bool; 9],
+    arrangements: Option<&[bool; 256]>,
+) -> fmt::Result {
+    for (count, _) in counts.iter().enumerate().filter(|(_, &c)| c) {
+        write!(f, "{}", count)?;
+        let arrangements = match arrangements {
+            Some(arrangements) => arrangements,
+            None => continue,
+        };
+        let (mut taken, mut left_out) = (String::new(), String::new());
+        for letter in hensel::letters(count).chars() {
+            let neighbors = (0..=255u8)
+                .find(|&n| n.count_ones() as usize == count && hensel::letter(n) == Some(letter))
+                .unwrap();
+            if arrangements[neighbors as usize] {
+                taken.push(letter);
+            } else {
+                left_out.push(letter);
+            }
+        }
+        if left_out.is_empty() {
+            continue;
+        }
+        if taken.len() <= left_out.len() + 1 {
+            write!(f, "{}", taken)?;
+        } else {
+            write!(f, "-{}", left_out)?;
+        }
+    }
+    Ok(())
+}
+
+// parse the number of states of a Generations rule
+fn parse_states(digits: &str) -> Result<u8, String> {
+    match digits.parse::<u32>() {
+        Ok(states) if (2..=255).contains(&states) => Ok(states as u8),
+        _ => Err(format!("a rule needs 2 to 255 states but got '{}'", digits)),
+    }
+}
+
+// Parses the standard rulestrings, ie. "B36/S23", "b3/s23", "S23/B3",
+// the older S/B notation "23/3" and the neighbourhood suffixes "B2/S34H"
+// and "B3/S23V". Generations rules give the number of states as a third
+// part, "B2/S/C3" or the older S/B/C "345/2/4". Isotropic non-totalistic
+// rules follow the counts with Hensel letters, "B2-a/S12" or "B3ai/S23-k".
+// "WireWorld" names the built in rule table.
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Rule, String> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err("empty rule".to_string());
+        }
+        if larger::is_larger(s) {
+            let (larger, states) = larger::parse(s)?;
+            return Ok(Rule {
+                birth: [false; 9],
+                survival: [false; 9],
+                neighborhood: Neighborhood::Moore,
+                states,
+                isotropic: None,
+                larger: Some(Box::new(larger)),
+                table: None,
+            });
+        }
+        if s.eq_ignore_ascii_case("WireWorld") {
+            return Rule::from_table(table::WIREWORLD);
+        }
+        // strip the neighbourhood suffix
+        let (body, neighborhood) = match s.chars().last() {
+            Some('V') | Some('v') => (&s[..s.len() - 1], Neighborhood::VonNeumann),
+            Some('H') | Some('h') => (&s[..s.len() - 1], Neighborhood::Hexagonal),
+            _ => (s, Neighborhood::Moore),
+        };
+
+        let mut birth = None;
+        let mut survival = None;
+        let mut states = None;
+        if body.contains(['B', 'b', 'S', 's']) {
+            // B/S notation, the halves may come in any order and
+            // the slash is optional ("B3S23")
+            let mut rest = body;
+            while !rest.is_empty() {
+                let mut chars = rest.chars();
+                let tag = chars.next().unwrap();
+                rest = chars.as_str();
+                // the half runs on over the digits and Hensel letters
+                let end = rest
+                    .find(|c: char| !(c.is_ascii_digit() || c == '-' || hensel::is_letter(c)))
+                    .unwrap_or(rest.len());
+                if let 'C' | 'c' | 'G' | 'g' = tag {
+                    if states.is_some() {
+                        return Err(format!("'{}' appears twice in rule", tag));
+                    }
+                    states = Some(parse_states(&rest[..end])?);
+                    rest = &rest[end..];
+                    if let Some(stripped) = rest.strip_prefix('/') {
+                        rest = stripped;
+                    }
+                    continue;
+                }
+                let counts = parse_half(&rest[..end], neighborhood)?;
+                let slot = match tag {
+                    'B' | 'b' => &mut birth,
+                    'S' | 's' => &mut survival,
+                    _ => return Err(format!("unexpected character '{}' in rule", tag)),
+                };
+                if slot.is_some() {
+                    return Err(format!("'{}' appears twice in rule", tag));
+                }
+                *slot = Some(counts);
+                rest = &rest[end..];
+                if let Some(stripped) = rest.strip_prefix('/') {
+                    rest = stripped;
+                }
+            }
+        } else {
+            // the old survival/birth notation, "23/3", or
+            // survival/birth/states for Generations, "345/2/4"
+            let mut halves = body.split('/');
+            let survive = halves.next().unwrap_or("");
+            let born = halves
+                .next()
+                .ok_or_else(|| format!("rule '{}' is missing a '/'", s))?;
+            if let Some(count) = halves.next() {
+                states = Some(parse_states(count)?);
+            }
+            if halves.next().is_some() {
+                return Err(format!("rule '{}' has too many '/'", s));
+            }
+            survival = Some(parse_half(survive, neighborhood)?);
+            birth = Some(parse_half(born, neighborhood)?);
+        }
+
+        let (birth, birth_arrangements) = birth.unwrap_or(([false; 9], None));
+        let (survival, survival_arrangements) = survival.unwrap_or(([false; 9], None));
+        let arrangements = Arrangements {
+            birth: birth_arrangements.unwrap_or_else(|| every_arrangement(&birth)),
+            survival: survival_arrangements.unwrap_or_else(|| every_arrangement(&survival)),
+        };
+        // letters that take every arrangement of a count ("B3cekainyqjr")
+        // leave a totalistic rule
+        let totalistic = arrangements.birth == every_arrangement(&birth)
+            && arrangements.survival == every_arrangement(&survival);
+        Ok(Rule {
+            birth,
+            survival,
+            neighborhood,
+            states: states.unwrap_or(2),
+            isotropic: if totalistic { None } else { Some(Box::new(arrangements)) },
+            larger: None,
+            table: None,
+        })
+    }
+}
+
+// write the rule back out in the canonical B/S notation, rule tables
+// by their name
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(table) = &self.table {
+            return write!(f, "{}", table.name());
+        }
+        if let Some(larger) = &self.larger {
+            return larger.write(f, self.states);
+        }
+        let isotropic = self.isotropic.as_deref();
+        write!(f, "B")?;
+        write_half(f, &self.birth, isotropic.map(|a| &a.birth))?;
+        write!(f, "/S")?;
+        write_half(f, &self.survival, isotropic.map(|a| &a.survival))?;
+        if self.states > 2 {
+            write!(f, "/C{}", self.states)?;
+        }
+        write!(f, "{}", self.neighborhood.suffix())
+    }
+}
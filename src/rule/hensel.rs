@@ -0,0 +1,244 @@
+// Hensel's notation for isotropic non-totalistic rules. The arrangements
+// of k live cells among the 8 Moore neighbours are grouped by rotation
+// and reflection and each group gets a letter, so "B2a" is a birth on
+// two live neighbours that touch each other at the side of the square.
+// Arrangements of more than four neighbours take the letter of their
+// complement; 0 and 8 have a single arrangement and no letters.
+//
+// The neighbours are bits of a byte in the order of
+// `Neighborhood::Moore.offsets()`.
+const NW: u8 = 1;
+const N: u8 = 1 << 1;
+const NE: u8 = 1 << 2;
+const W: u8 = 1 << 3;
+const E: u8 = 1 << 4;
+const SW: u8 = 1 << 5;
+const S: u8 = 1 << 6;
+const SE: u8 = 1 << 7;
+
+// the letters of each neighbour count, in the order they are written
+const LETTERS: [&str; 9] = [
+    "",
+    "ce",
+    "cekain",
+    "cekainyqjr",
+    "cekainyqjrtwz",
+    "cekainyqjr",
+    "cekain",
+    "ce",
+    "",
+];
+
+// an arrangement of each letter of 1 to 4 neighbours, as Golly has them
+const SHAPES: [&[(char, u8)]; 5] = [
+    &[],
+    &[('c', NW), ('e', N)],
+    &[
+        ('c', NW | NE),
+        ('e', N | W),
+        ('k', NW | E),
+        ('a', NW | N),
+        ('i', W | E),
+        ('n', NE | SW),
+    ],
+    &[
+        ('c', NW | NE | SW),
+        ('e', N | W | E),
+        ('k', N | E | SW),
+        ('a', NW | N | W),
+        ('i', NW | N | NE),
+        ('n', NW | NE | W),
+        ('y', NW | E | SW),
+        ('q', N | NE | SW),
+        ('j', N | NE | W),
+        ('r', NW | W | E),
+    ],
+    &[
+        ('c', NW | NE | SW | SE),
+        ('e', N | W | E | S),
+        ('k', NW | N | E | SW),
+        ('a', NW | N | NE | W),
+        ('i', NW | NE | W | E),
+        ('n', NW | N | NE | SW),
+        ('y', N | NE | W | SW),
+        ('q', N | NE | E | SW),
+        ('j', N | W | E | SW),
+        ('r', NW | N | W | E),
+        ('t', NW | NE | E | SW),
+        ('w', NW | W | E | SW),
+        ('z', NE | W | E | SW),
+    ],
+];
+
+// the (delta_row, delta_col) of each neighbour bit
+const OFFSETS: [(i32, i32); 8] = [
+    (-1, -1), (-1, 0), (-1, 1),
+    (0, -1), (0, 1),
+    (1, -1), (1, 0), (1, 1),
+];
+
+// one of the rotations or reflections of the neighbours
+type Transform = fn(i32, i32) -> (i32, i32);
+
+// can the character be one of the letters
+pub fn is_letter(c: char) -> bool {
+    LETTERS[4].contains(c)
+}
+
+// the letters of a neighbour count in the order they are written
+pub fn letters(count: usize) -> &'static str {
+    LETTERS[count]
+}
+
+// the smallest of the eight rotations and reflections of an arrangement,
+// the same for every arrangement with the same letter
+fn canonical(neighbors: u8) -> u8 {
+    let transforms: [Transform; 8] = [
+        |r, c| (r, c),
+        |r, c| (c, -r),
+        |r, c| (-r, -c),
+        |r, c| (-c, r),
+        |r, c| (r, -c),
+        |r, c| (-r, c),
+        |r, c| (c, r),
+        |r, c| (-c, -r),
+    ];
+    transforms
+        .iter()
+        .map(|t| {
+            let mut moved = 0;
+            for (bit, &(r, c)) in OFFSETS.iter().enumerate() {
+                if neighbors >> bit & 1 == 1 {
+                    let to = t(r, c);
+                    moved |= 1 << OFFSETS.iter().position(|&o| o == to).unwrap();
+                }
+            }
+            moved
+        })
+        .min()
+        .unwrap()
+}
+
+// the letter of an arrangement of live neighbours, None for 0 and 8
+pub fn letter(neighbors: u8) -> Option<char> {
+    let count = neighbors.count_ones() as usize;
+    let neighbors = if count > 4 { !neighbors } else { neighbors };
+    let shape = canonical(neighbors);
+    SHAPES[count.min(8 - count)]
+        .iter()
+        .find(|&&(_, other)| canonical(other) == shape)
+        .map(|&(letter, _)| letter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Cell, Rule};
+
+    // the number of arrangements of each letter, from the rotations and
+    // reflections of its shape in Hensel's table
+    const SIZES: [&[(char, usize)]; 5] = [
+        &[],
+        &[('c', 4), ('e', 4)],
+        &[('c', 4), ('e', 4), ('k', 8), ('a', 8), ('i', 2), ('n', 2)],
+        &[
+            ('c', 4),
+            ('e', 4),
+            ('k', 4),
+            ('a', 4),
+            ('i', 4),
+            ('n', 8),
+            ('y', 4),
+            ('q', 8),
+            ('j', 8),
+            ('r', 8),
+        ],
+        &[
+            ('c', 1),
+            ('e', 1),
+            ('k', 8),
+            ('a', 8),
+            ('i', 4),
+            ('n', 8),
+            ('y', 4),
+            ('q', 4),
+            ('j', 8),
+            ('r', 8),
+            ('t', 8),
+            ('w', 4),
+            ('z', 4),
+        ],
+    ];
+
+    #[test]
+    fn every_arrangement_has_a_letter_of_its_count() {
+        for neighbors in 0..=255u8 {
+            let count = neighbors.count_ones() as usize;
+            match letter(neighbors) {
+                Some(l) => {
+                    assert!(letters(count).contains(l), "{:08b} is {}{}", neighbors, count, l)
+                }
+                None => assert!(count == 0 || count == 8, "{:08b}", neighbors),
+            }
+        }
+    }
+
+    #[test]
+    fn letters_group_the_rotations_and_reflections() {
+        for (count, sizes) in SIZES.iter().enumerate() {
+            for &(l, size) in sizes.iter() {
+                let with = |count: usize| {
+                    (0..=255u8)
+                        .filter(|&n| n.count_ones() as usize == count && letter(n) == Some(l))
+                        .count()
+                };
+                assert_eq!(with(count), size, "{}{}", count, l);
+                // more than four neighbours take the letter of their complement
+                assert_eq!(with(8 - count), size, "{}{}", 8 - count, l);
+            }
+        }
+        // and between them the letters of a count cover all of its
+        // arrangements, C(8, count) of them
+        for (count, arrangements) in [(1, 8), (2, 28), (3, 56), (4, 70)] {
+            let sizes = SIZES[count].iter().map(|&(_, size)| size);
+            assert_eq!(sizes.sum::<usize>(), arrangements);
+        }
+        // a few by hand, turned away from the shapes above
+        assert_eq!(letter(S | E), Some('e'));
+        assert_eq!(letter(N | S), Some('i'));
+        assert_eq!(letter(SE | W), Some('k'));
+        assert_eq!(letter(NW | SE), Some('n'));
+        assert_eq!(letter(SW | S | SE), Some('i'));
+        assert_eq!(letter(NE | E | SE | S), Some('a'));
+        assert_eq!(letter(!(N | W)), Some('e'));
+        assert_eq!(letter(!(NE | SE | SW)), Some('c'));
+    }
+
+    #[test]
+    fn rules_take_only_the_letters_given() {
+        let rule: Rule = "B2-a3ce/S1e".parse().unwrap();
+        for neighbors in 0..=255u8 {
+            let count = neighbors.count_ones();
+            let born = match (count, letter(neighbors)) {
+                (2, Some(l)) => l != 'a',
+                (3, Some(l)) => l == 'c' || l == 'e',
+                _ => false,
+            };
+            let survives = count == 1 && letter(neighbors) == Some('e');
+            assert_eq!(rule.next_cell_around(Cell::DEAD, neighbors) == Cell::ALIVE, born);
+            assert_eq!(rule.next_cell_around(Cell::ALIVE, neighbors) == Cell::ALIVE, survives);
+        }
+    }
+
+    #[test]
+    fn rules_read_back_what_they_write() {
+        for text in ["B2-a/S12", "B2ce3ai/S1e2-k", "B3/S23-a4i", "B2in3-jr4w/S01c2-a8"] {
+            let rule: Rule = text.parse().unwrap();
+            assert_eq!(rule.to_string(), text);
+            assert_eq!(rule.to_string().parse::<Rule>().unwrap(), rule);
+        }
+        // letters come out in the order of the table and the shorter way round
+        let rule: Rule = "B3yrqjnia/S2kain".parse().unwrap();
+        assert_eq!(rule.to_string(), "B3-cek/S2-ce");
+    }
+}
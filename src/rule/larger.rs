@@ -0,0 +1,411 @@
+// Larger than Life, Evans' family of rules looking further than the
+// 3x3 block, written as Golly does:
+//
+//   R5,C0,M1,S34..58,B34..45,NM
+//
+// R is the range, C the number of states (0 for two states, 3 or more
+// for Generations), M1 counts the middle cell as its own neighbour, S and
+// B give the counts for survival and birth and N the shape of the
+// neighbourhood. A count can be a single number or a range written
+// "34..58" or "34-58", and more of them may follow ("S2,4-6,9").
+use std::fmt;
+
+use crate::{Cell, Topology};
+
+// the largest range Golly takes
+const MAX_RANGE: u32 = 500;
+
+// the shape of the neighbourhood within the range
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LargerShape {
+    // the whole (2R+1)x(2R+1) square ("NM")
+    Moore,
+    // the diamond of cells R or fewer steps away ("NN")
+    VonNeumann,
+    // the cells within R + 0.5 of the middle ("NC")
+    Circular,
+    // the row and the column through the middle ("N+")
+    Cross,
+    // a hexagon on the skewed grid, as the 'H' suffix of Life-like rules ("NH")
+    Hexagonal,
+    // a weight from 0 to 15 for each cell of the (2R+1)x(2R+1) square,
+    // row by row, written as hex digits ("NW"); the middle cell's
+    // weight stands in for M
+    Weighted(Vec<u8>),
+}
+
+// A Larger than Life rule, the states are kept by the `Rule`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Larger {
+    range: u32,
+    middle: bool,
+    shape: LargerShape,
+    // the (lowest, highest) counts of each range
+    survival: Vec<(u32, u32)>,
+    birth: Vec<(u32, u32)>,
+}
+
+impl Larger {
+    pub fn range(&self) -> u32 {
+        self.range
+    }
+
+    pub fn shape(&self) -> &LargerShape {
+        &self.shape
+    }
+
+    // does a dead cell with this count come alive
+    pub fn born(&self, count: u32) -> bool {
+        self.birth.iter().any(|&(low, high)| low <= count && count <= high)
+    }
+
+    // does a live cell with this count stay alive
+    pub fn survives(&self, count: u32) -> bool {
+        self.survival.iter().any(|&(low, high)| low <= count && count <= high)
+    }
+
+    // the (delta_row, delta_col, weight) of every cell the count looks at
+    pub fn weights(&self) -> Vec<(i32, i32, u32)> {
+        let r = self.range as i32;
+        let mut weights = Vec::new();
+        for dr in -r..=r {
+            for dc in -r..=r {
+                let weight = match &self.shape {
+                    LargerShape::Weighted(mask) => {
+                        mask[((dr + r) * (2 * r + 1) + dc + r) as usize] as u32
+                    }
+                    _ if dr == 0 && dc == 0 => self.middle as u32,
+                    _ => self.span(dr).is_some_and(|(low, high)| low <= dc && dc <= high) as u32,
+                };
+                if weight > 0 {
+                    weights.push((dr, dc, weight));
+                }
+            }
+        }
+        weights
+    }
+
+    // the (lowest, highest) delta_col of the cells in the row delta_row
+    // away from the middle, for the shapes without weights
+    fn span(&self, dr: i32) -> Option<(i32, i32)> {
+        let r = self.range as i32;
+        let half = match self.shape {
+            LargerShape::Moore => r,
+            LargerShape::VonNeumann => r - dr.abs(),
+            LargerShape::Circular => ((r * r + r - dr * dr) as f64).sqrt() as i32,
+            LargerShape::Cross if dr == 0 => r,
+            LargerShape::Cross => 0,
+            LargerShape::Hexagonal => return Some(((dr - r).max(-r), (dr + r).min(r))),
+            LargerShape::Weighted(_) => return None,
+        };
+        Some((-half, half))
+    }
+
+    // The count of every cell of a grid. The live cells are copied into
+    // a grid with a margin of the range all round, filled in through the
+    // topology, so every neighbourhood can be read without wrapping. The
+    // Moore square is then a lookup in a summed-area table, the other
+    // shapes add up one span per row out of running sums along the rows
+    // and only the weighted masks go cell by cell.
+    pub fn counts(&self, cells: &[Cell], width: u32, height: u32, topology: Topology) -> Vec<u32> {
+        let r = self.range as i64;
+        let (w, h) = (width as i64, height as i64);
+        let (padded_width, padded_height) = (w + 2 * r, h + 2 * r);
+        let mut live = vec![0u32; (padded_width * padded_height) as usize];
+        for pr in 0..padded_height {
+            for pc in 0..padded_width {
+                if let Some((row, col)) = topology.resolve(pr - r, pc - r, height, width) {
+                    let cell = cells[(row * width + col) as usize];
+                    live[(pr * padded_width + pc) as usize] = (cell == Cell::ALIVE) as u32;
+                }
+            }
+        }
+
+        let mut counts = vec![0u32; (w * h) as usize];
+        match &self.shape {
+            LargerShape::Moore => {
+                // table[y][x] holds the live cells above and left of y, x
+                let stride = (padded_width + 1) as usize;
+                let mut table = vec![0u32; stride * (padded_height + 1) as usize];
+                for y in 0..padded_height as usize {
+                    let mut row_sum = 0;
+                    for x in 0..padded_width as usize {
+                        row_sum += live[y * padded_width as usize + x];
+                        table[(y + 1) * stride + x + 1] = table[y * stride + x + 1] + row_sum;
+                    }
+                }
+                let size = (2 * r + 1) as usize;
+                for row in 0..h as usize {
+                    for col in 0..w as usize {
+                        let (top, left) = (row, col);
+                        let (bottom, right) = (row + size, col + size);
+                        counts[row * w as usize + col] = table[bottom * stride + right]
+                            + table[top * stride + left]
+                            - table[top * stride + right]
+                            - table[bottom * stride + left];
+                    }
+                }
+            }
+            LargerShape::Weighted(_) => {
+                let weights = self.weights();
+                for row in 0..h {
+                    for col in 0..w {
+                        let mut count = 0;
+                        for &(dr, dc, weight) in &weights {
+                            let idx = (row + r + dr as i64) * padded_width + col + r + dc as i64;
+                            count += weight * live[idx as usize];
+                        }
+                        counts[(row * w + col) as usize] = count;
+                    }
+                }
+            }
+            _ => {
+                // sums[y][x] holds the live cells of row y left of x
+                let stride = (padded_width + 1) as usize;
+                let mut sums = vec![0u32; stride * padded_height as usize];
+                for y in 0..padded_height as usize {
+                    for x in 0..padded_width as usize {
+                        let cell = live[y * padded_width as usize + x];
+                        sums[y * stride + x + 1] = sums[y * stride + x] + cell;
+                    }
+                }
+                let spans: Vec<(i64, i64)> = (-r..=r)
+                    .map(|dr| {
+                        let (low, high) = self.span(dr as i32).unwrap_or((0, -1));
+                        (low as i64, high as i64)
+                    })
+                    .collect();
+                for row in 0..h {
+                    for col in 0..w {
+                        let mut count = 0;
+                        for (i, &(low, high)) in spans.iter().enumerate() {
+                            let y = (row + i as i64) as usize * stride;
+                            let (left, right) = (col + r + low, col + r + high + 1);
+                            count += sums[y + right as usize] - sums[y + left as usize];
+                        }
+                        counts[(row * w + col) as usize] = count;
+                    }
+                }
+            }
+        }
+
+        // the spans take in the middle cell, M0 leaves it out again
+        if !self.middle && self.shape.uniform() {
+            for (count, &cell) in counts.iter_mut().zip(cells.iter()) {
+                *count -= (cell == Cell::ALIVE) as u32;
+            }
+        }
+        counts
+    }
+
+    // write the rule back out, states being the number of states
+    pub fn write(&self, f: &mut fmt::Formatter, states: u8) -> fmt::Result {
+        let states = if states > 2 { states } else { 0 };
+        write!(f, "R{},C{},M{},S", self.range, states, self.middle as u8)?;
+        write_counts(f, &self.survival)?;
+        write!(f, ",B")?;
+        write_counts(f, &self.birth)?;
+        match &self.shape {
+            LargerShape::Moore => write!(f, ",NM"),
+            LargerShape::VonNeumann => write!(f, ",NN"),
+            LargerShape::Circular => write!(f, ",NC"),
+            LargerShape::Cross => write!(f, ",N+"),
+            LargerShape::Hexagonal => write!(f, ",NH"),
+            LargerShape::Weighted(mask) => {
+                write!(f, ",NW")?;
+                for &weight in mask {
+                    write!(f, "{:x}", weight)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl LargerShape {
+    // every cell of the shape counts once
+    fn uniform(&self) -> bool {
+        !matches!(self, LargerShape::Weighted(_))
+    }
+}
+
+fn write_counts(f: &mut fmt::Formatter, counts: &[(u32, u32)]) -> fmt::Result {
+    for (i, &(low, high)) in counts.iter().enumerate() {
+        if i > 0 {
+            write!(f, ",")?;
+        }
+        if low == high {
+            write!(f, "{}", low)?;
+        } else {
+            write!(f, "{}..{}", low, high)?;
+        }
+    }
+    Ok(())
+}
+
+// is the rulestring a Larger than Life one, "R" and the range
+pub fn is_larger(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some('R') | Some('r')) && chars.next().is_some_and(|c| c.is_ascii_digit())
+}
+
+// read a count "5" or a range "34..58" / "34-58"
+fn parse_count(text: &str) -> Result<(u32, u32), String> {
+    let number = |n: &str| {
+        n.trim()
+            .parse::<u32>()
+            .map_err(|_| format!("bad count '{}' in rule", text))
+    };
+    let (low, high) = match text.split_once("..").or_else(|| text.split_once('-')) {
+        Some((low, high)) => (number(low)?, number(high)?),
+        None => (number(text)?, number(text)?),
+    };
+    if low > high {
+        return Err(format!("count range '{}' runs backwards", text));
+    }
+    Ok((low, high))
+}
+
+// Parse a Larger than Life rulestring, returning the rule and the
+// number of states
+pub fn parse(s: &str) -> Result<(Larger, u8), String> {
+    let mut range = None;
+    let mut states = 2;
+    let mut middle = false;
+    let mut shape = LargerShape::Moore;
+    let mut survival = Vec::new();
+    let mut birth = Vec::new();
+    // which list a bare count goes on, 'S' or 'B'
+    let mut list = None;
+
+    for part in s.split(',') {
+        let part = part.trim();
+        let mut chars = part.chars();
+        let tag = chars.next().ok_or_else(|| format!("empty part in rule '{}'", s))?;
+        if tag.is_ascii_digit() {
+            match list {
+                Some('S') => survival.push(parse_count(part)?),
+                Some('B') => birth.push(parse_count(part)?),
+                _ => return Err(format!("count '{}' has no 'S' or 'B' in front of it", part)),
+            }
+            continue;
+        }
+        let value = chars.as_str();
+        let number = || {
+            value
+                .parse::<u32>()
+                .map_err(|_| format!("bad number '{}' in rule", part))
+        };
+        list = None;
+        match tag.to_ascii_uppercase() {
+            'R' => {
+                let r = number()?;
+                if r == 0 || r > MAX_RANGE {
+                    return Err(format!("range {} is out of 1..{}", r, MAX_RANGE));
+                }
+                range = Some(r);
+            }
+            'C' => {
+                states = match number()? {
+                    0..=2 => 2,
+                    c if c <= 255 => c as u8,
+                    _ => return Err(format!("a rule needs 2 to 255 states but got '{}'", value)),
+                }
+            }
+            'M' => {
+                middle = match value {
+                    "0" => false,
+                    "1" => true,
+                    _ => return Err(format!("'M' is 0 or 1 but got '{}'", value)),
+                }
+            }
+            half @ ('S' | 'B') => {
+                let counts = if half == 'S' { &mut survival } else { &mut birth };
+                if !value.is_empty() {
+                    counts.push(parse_count(value)?);
+                }
+                list = Some(half);
+            }
+            'N' => {
+                let mut kind = value.chars();
+                shape = match kind.next().map(|c| c.to_ascii_uppercase()) {
+                    Some('M') => LargerShape::Moore,
+                    Some('N') => LargerShape::VonNeumann,
+                    Some('C') => LargerShape::Circular,
+                    Some('+') => LargerShape::Cross,
+                    Some('H') => LargerShape::Hexagonal,
+                    Some('W') => LargerShape::Weighted(
+                        kind.map(|c| {
+                            c.to_digit(16)
+                                .map(|d| d as u8)
+                                .ok_or_else(|| format!("bad weight '{}' in rule", c))
+                        })
+                        .collect::<Result<Vec<_>, _>>()?,
+                    ),
+                    _ => return Err(format!("unknown neighbourhood '{}'", part)),
+                };
+            }
+            _ => return Err(format!("unexpected '{}' in rule", part)),
+        }
+    }
+
+    let range = range.ok_or_else(|| format!("rule '{}' is missing its range", s))?;
+    if let LargerShape::Weighted(mask) = &shape {
+        let side = 2 * range as usize + 1;
+        if mask.len() != side * side {
+            return Err(format!(
+                "a range {} weighted neighbourhood needs {} weights but got {}",
+                range,
+                side * side,
+                mask.len()
+            ));
+        }
+    }
+    let larger = Larger {
+        range,
+        middle,
+        shape,
+        survival,
+        birth,
+    };
+    Ok((larger, states))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the count at row, col with a single live cell at live
+    fn count(
+        rule: &str,
+        topology: Topology,
+        (width, height): (u32, u32),
+        live: (u32, u32),
+        at: (u32, u32),
+    ) -> u32 {
+        let (larger, _) = parse(rule).unwrap();
+        let mut cells = vec![Cell::DEAD; (width * height) as usize];
+        cells[(live.0 * width + live.1) as usize] = Cell::ALIVE;
+        larger.counts(&cells, width, height, topology)[(at.0 * width + at.1) as usize]
+    }
+
+    #[test]
+    fn counts_across_the_seams_of_a_sphere() {
+        // around 1,0 of a 5x5 sphere the range reaches two columns past
+        // the left edge. That edge is joined to the top one, so col -1
+        // reads row 0 and col -2 reads row 1: only (3, -1) lands on 0,3
+        let rule = "R2,C0,M0,S1..24,B1..24,NM";
+        assert_eq!(count(rule, Topology::Sphere, (5, 5), (0, 3), (1, 0)), 1);
+    }
+
+    #[test]
+    fn counts_twice_around_a_small_klein_bottle() {
+        // rows -3..=3 around row 0 of a 5 wide, 2 high Klein bottle wrap
+        // -2, -1, -1, 0, 0, 1 and 1 times. The odd ones mirror the column
+        // and read 1,1 from cols 3 and -2 (-1 and 3), the even ones
+        // from col 1 alone (-3 and 1)
+        let rule = "R3,C0,M0,S1..48,B1..48,NM";
+        let klein = Topology::KleinBottle { twisted_rows: false };
+        assert_eq!(count(rule, klein, (5, 2), (1, 1), (0, 0)), 6);
+    }
+}
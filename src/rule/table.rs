@@ -0,0 +1,618 @@
+// Golly's rule tables (.rule files), automata given as a list of
+// transitions rather than a rulestring:
+//
+//   @RULE WireWorld
+//   @TABLE
+//   n_states:4
+//   neighborhood:Moore
+//   symmetries:permute
+//   var a={0,1,2,3}
+//   1,a,a,a,a,a,a,a,a,2      # C,N,NE,E,SE,S,SW,W,NW,C'
+//
+// Each transition lists the state of the cell, of its neighbours going
+// clockwise from the north and the state it turns into. An entry may be
+// a variable standing for a set of states; a variable used more than
+// once in a transition takes the same state everywhere, inline sets
+// ("{1,2}") don't. The first transition to match wins and a cell no
+// transition matches stays as it is. The symmetries add the rotated and
+// reflected copies of every transition. @COLORS gives the colour of each
+// state as "state r g b". @TREE rules aren't read.
+use std::collections::HashMap;
+
+// the most transitions a table may grow to once the variables and
+// symmetries are written out
+const MAX_TRANSITIONS: usize = 1 << 18;
+
+// Golly's WireWorld.rule, built in so "WireWorld" works as a rulestring
+pub const WIREWORLD: &str = "@RULE WireWorld
+@TABLE
+n_states:4
+neighborhood:Moore
+symmetries:permute
+var a={0,1,2,3}
+var b={0,1,2,3}
+var c={0,1,2,3}
+var d={0,1,2,3}
+var e={0,1,2,3}
+var f={0,1,2,3}
+var g={0,1,2,3}
+var h={0,1,2,3}
+var i={0,2,3}
+var j={0,2,3}
+var k={0,2,3}
+var l={0,2,3}
+var m={0,2,3}
+var n={0,2,3}
+# an electron head becomes a tail and a tail becomes wire
+1,a,b,c,d,e,f,g,h,2
+2,a,b,c,d,e,f,g,h,3
+# wire next to one or two heads becomes a head
+3,i,j,k,l,m,n,a,1,1
+3,i,j,k,l,m,n,1,1,1
+@COLORS
+0 48 48 48
+1 0 128 255
+2 255 255 255
+3 255 128 0
+";
+
+// the neighbours of each neighborhood in the order the transitions list
+// them, as (delta_row, delta_col)
+const MOORE: &[(i32, i32)] = &[
+    (-1, 0), (-1, 1), (0, 1), (1, 1),
+    (1, 0), (1, -1), (0, -1), (-1, -1),
+];
+const VON_NEUMANN: &[(i32, i32)] = &[(-1, 0), (0, 1), (1, 0), (0, -1)];
+// a hex grid mapped onto the square grid as for the 'H' rule suffix
+const HEXAGONAL: &[(i32, i32)] = &[(-1, 0), (0, 1), (1, 1), (1, 0), (0, -1), (-1, -1)];
+const ONE_DIMENSIONAL: &[(i32, i32)] = &[(0, -1), (0, 1)];
+
+// An automaton read from a rule table. For every position (the cell and
+// then its neighbours) and state there's a bitset of the transitions
+// which take that state there, so a cell is looked up by and-ing one
+// bitset per position and taking the first transition left.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RuleTable {
+    name: String,
+    states: u8,
+    offsets: &'static [(i32, i32)],
+    // the bitsets, indexed by position * states + state
+    matches: Vec<Vec<u64>>,
+    outputs: Vec<u8>,
+    // the next state of a dead cell with dead neighbours
+    quiet: u8,
+    // the (r, g, b) given to each state by @COLORS
+    colors: Vec<Option<(u8, u8, u8)>>,
+}
+
+// a transition with the variables written out; the states each
+// position takes and the state it turns into
+type Transition = (Vec<Vec<u8>>, u8);
+
+// an entry of a transition; the states it takes and the variable it
+// came from, None for numbers and inline sets
+#[derive(Clone, Debug)]
+struct Entry {
+    states: Vec<u8>,
+    variable: Option<String>,
+}
+
+impl RuleTable {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn states(&self) -> u8 {
+        self.states
+    }
+
+    // the (delta_row, delta_col) of the neighbours, in the table's order
+    pub fn offsets(&self) -> &'static [(i32, i32)] {
+        self.offsets
+    }
+
+    // does a dead cell with dead neighbours come alive
+    pub fn has_b0(&self) -> bool {
+        self.quiet != 0
+    }
+
+    // the colour @COLORS gives a state
+    pub fn color(&self, state: u8) -> Option<(u8, u8, u8)> {
+        self.colors.get(state as usize).cloned().flatten()
+    }
+
+    // the next state of a cell given the states of its neighbours in
+    // the order of `offsets`
+    pub fn next(&self, cell: u8, neighbors: &[u8]) -> u8 {
+        if cell == 0 && neighbors.iter().all(|&n| n == 0) {
+            return self.quiet;
+        }
+        self.lookup(cell, neighbors)
+    }
+
+    fn lookup(&self, cell: u8, neighbors: &[u8]) -> u8 {
+        let states = self.states as usize;
+        // states the table doesn't have, left over from another rule,
+        // match no transition
+        if cell >= self.states || neighbors.iter().any(|&n| n >= self.states) {
+            return cell;
+        }
+        let words = self.outputs.len().div_ceil(64);
+        for word in 0..words {
+            let mut bits = self.matches[cell as usize][word];
+            for (position, &state) in neighbors.iter().enumerate() {
+                if bits == 0 {
+                    break;
+                }
+                bits &= self.matches[(position + 1) * states + state as usize][word];
+            }
+            if bits != 0 {
+                return self.outputs[word * 64 + bits.trailing_zeros() as usize];
+            }
+        }
+        cell
+    }
+}
+
+// the permutations of the neighbours the symmetries stand for
+fn symmetries(name: &str, neighbors: usize) -> Result<Vec<Vec<usize>>, String> {
+    let (rotations, reflect) = match name {
+        "none" => (1, false),
+        "rotate2" => (2, false),
+        "rotate3" => (3, false),
+        "rotate4" => (4, false),
+        "rotate6" => (6, false),
+        "rotate8" => (8, false),
+        "reflect" | "reflect_horizontal" => (1, true),
+        "rotate4reflect" => (4, true),
+        "rotate6reflect" => (6, true),
+        "rotate8reflect" => (8, true),
+        // handled by `permutations`
+        "permute" => return Ok(Vec::new()),
+        _ => return Err(format!("unknown symmetries '{}'", name)),
+    };
+    if !neighbors.is_multiple_of(rotations) {
+        return Err(format!("symmetries '{}' don't fit this neighborhood", name));
+    }
+    let step = neighbors / rotations;
+    let mut orders = Vec::new();
+    for rotation in 0..rotations {
+        let rotated: Vec<usize> =
+            (0..neighbors).map(|i| (i + rotation * step) % neighbors).collect();
+        if reflect {
+            // mirror about the first neighbour, the north one
+            orders.push(rotated.iter().map(|&i| (neighbors - i) % neighbors).collect());
+        }
+        orders.push(rotated);
+    }
+    Ok(orders)
+}
+
+// every distinct ordering of the neighbour entries, for "permute"
+fn permutations(entries: &[Vec<u8>]) -> Vec<Vec<Vec<u8>>> {
+    let mut sorted = entries.to_vec();
+    sorted.sort();
+    let mut out = vec![sorted.clone()];
+    // step through the orderings in lexicographic order, which skips
+    // the repeats of equal entries
+    loop {
+        let last = sorted.len().saturating_sub(1);
+        let i = match (0..last).rev().find(|&i| sorted[i] < sorted[i + 1]) {
+            Some(i) => i,
+            None => return out,
+        };
+        let j = (i + 1..sorted.len()).rev().find(|&j| sorted[j] > sorted[i]).unwrap();
+        sorted.swap(i, j);
+        sorted[i + 1..].reverse();
+        out.push(sorted.clone());
+    }
+}
+
+// split a transition into its entries, either comma separated or one
+// character each when it has no commas
+fn split_entries(line: &str) -> Result<Vec<String>, String> {
+    if !line.contains(',') {
+        return Ok(line.chars().filter(|c| !c.is_whitespace()).map(|c| c.to_string()).collect());
+    }
+    let mut entries = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0;
+    for c in line.chars() {
+        match c {
+            '{' => depth += 1,
+            '}' if depth == 0 => return Err("unbalanced '}'".to_string()),
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                entries.push(current.trim().to_string());
+                current.clear();
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    if depth != 0 {
+        return Err("unbalanced '{'".to_string());
+    }
+    entries.push(current.trim().to_string());
+    Ok(entries)
+}
+
+// the states of "{0,1,b}", a state number or a variable
+fn parse_states(
+    text: &str,
+    states: u8,
+    variables: &HashMap<String, Vec<u8>>,
+) -> Result<Vec<u8>, String> {
+    if let Some(inner) = text.strip_prefix('{').and_then(|t| t.strip_suffix('}')) {
+        let mut set = Vec::new();
+        for part in split_entries(&format!("{},", inner))? {
+            if part.is_empty() {
+                continue;
+            }
+            for state in parse_states(&part, states, variables)? {
+                if !set.contains(&state) {
+                    set.push(state);
+                }
+            }
+        }
+        return Ok(set);
+    }
+    if let Ok(state) = text.parse::<u32>() {
+        if state >= states as u32 {
+            return Err(format!("state {} is out of range for {} states", state, states));
+        }
+        return Ok(vec![state as u8]);
+    }
+    variables
+        .get(text)
+        .cloned()
+        .ok_or_else(|| format!("unknown variable '{}'", text))
+}
+
+// the number of states of a table
+fn parse_count(value: &str) -> Result<u8, String> {
+    match value.parse::<u32>() {
+        Ok(states) if (2..=255).contains(&states) => Ok(states as u8),
+        _ => Err(format!("a table needs 2 to 255 states but got '{}'", value)),
+    }
+}
+
+// Read a .rule file, only the @TABLE and @COLORS sections are used
+pub fn parse(text: &str) -> Result<RuleTable, String> {
+    let mut name = None;
+    let mut section = "";
+    let mut states: Option<u8> = None;
+    let mut offsets = MOORE;
+    let mut symmetry = "none".to_string();
+    let mut variables: HashMap<String, Vec<u8>> = HashMap::new();
+    let mut transitions: Vec<(Vec<Entry>, Entry)> = Vec::new();
+    let mut colors = Vec::new();
+
+    for (number, line) in text.lines().enumerate() {
+        let error = |e: String| format!("rule table line {}: {}", number + 1, e);
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('@') {
+            let mut words = header.split_whitespace();
+            section = match words.next().unwrap_or("") {
+                "RULE" => {
+                    name = words.next().map(|n| n.to_string());
+                    "RULE"
+                }
+                "TABLE" => "TABLE",
+                "COLORS" => "COLORS",
+                "TREE" => return Err(error("rule trees (@TREE) aren't supported".to_string())),
+                _ => "",
+            };
+            continue;
+        }
+        match section {
+            "TABLE" => {
+                if let Some((key, value)) = line.split_once(':') {
+                    let value = value.trim();
+                    match key.trim() {
+                        "n_states" => states = Some(parse_count(value).map_err(error)?),
+                        "neighborhood" => {
+                            offsets = match value {
+                                "Moore" => MOORE,
+                                "vonNeumann" => VON_NEUMANN,
+                                "hexagonal" => HEXAGONAL,
+                                "oneDimensional" => ONE_DIMENSIONAL,
+                                _ => return Err(error(format!("unknown neighborhood '{}'", value))),
+                            }
+                        }
+                        "symmetries" => symmetry = value.to_string(),
+                        _ => return Err(error(format!("unknown setting '{}'", key.trim()))),
+                    }
+                    continue;
+                }
+                let n_states = states.ok_or_else(|| error("n_states must come first".to_string()))?;
+                if let Some(declaration) = line.strip_prefix("var ") {
+                    let (var, set) = declaration
+                        .split_once('=')
+                        .ok_or_else(|| error("expected 'var name={..}'".to_string()))?;
+                    let set = parse_states(set.trim(), n_states, &variables).map_err(error)?;
+                    variables.insert(var.trim().to_string(), set);
+                    continue;
+                }
+                let entries = split_entries(line).map_err(error)?;
+                if entries.len() != offsets.len() + 2 {
+                    return Err(error(format!(
+                        "a transition needs {} entries but has {}",
+                        offsets.len() + 2,
+                        entries.len()
+                    )));
+                }
+                let mut parsed = Vec::new();
+                for entry in &entries {
+                    let set = parse_states(entry, n_states, &variables).map_err(error)?;
+                    let variable = if variables.contains_key(entry.as_str()) {
+                        Some(entry.clone())
+                    } else {
+                        None
+                    };
+                    parsed.push(Entry { states: set, variable });
+                }
+                let output = parsed.pop().unwrap();
+                if output.states.len() != 1 && output.variable.is_none() {
+                    return Err(error("the output must be a single state".to_string()));
+                }
+                transitions.push((parsed, output));
+            }
+            "COLORS" => {
+                let numbers = line
+                    .split_whitespace()
+                    .map(|n| n.parse::<u8>().map_err(|_| error(format!("bad colour '{}'", line))))
+                    .collect::<Result<Vec<_>, _>>()?;
+                match numbers[..] {
+                    [state, r, g, b] => {
+                        let state = state as usize;
+                        if colors.len() <= state {
+                            colors.resize(state + 1, None);
+                        }
+                        colors[state] = Some((r, g, b));
+                    }
+                    // a gradient over the live states
+                    [r1, g1, b1, r2, g2, b2] => {
+                        let last = states.unwrap_or(2).max(2) as usize - 1;
+                        colors.resize(colors.len().max(last + 1), None);
+                        let mix = |a: u8, b: u8, i: usize| {
+                            let t = if last > 1 { (i - 1) as f64 / (last - 1) as f64 } else { 0.0 };
+                            (a as f64 + (b as f64 - a as f64) * t).round() as u8
+                        };
+                        for (i, color) in colors.iter_mut().enumerate().take(last + 1).skip(1) {
+                            *color = Some((mix(r1, r2, i), mix(g1, g2, i), mix(b1, b2, i)));
+                        }
+                    }
+                    _ => return Err(error(format!("bad colour line '{}'", line))),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let states = states.ok_or_else(|| "the rule table has no n_states".to_string())?;
+    let name = name.unwrap_or_else(|| "table".to_string());
+    let orders = symmetries(&symmetry, offsets.len())?;
+
+    // write out the variables used more than once and then the symmetries
+    let mut expanded: Vec<Transition> = Vec::new();
+    for (inputs, output) in &transitions {
+        for (inputs, output) in bind(inputs, output)? {
+            let (cell, neighbors) = (inputs[0].clone(), &inputs[1..]);
+            let orderings = if symmetry == "permute" {
+                permutations(neighbors)
+            } else {
+                orders
+                    .iter()
+                    .map(|order| order.iter().map(|&i| neighbors[i].clone()).collect())
+                    .collect()
+            };
+            for ordering in orderings {
+                let mut entries = vec![cell.clone()];
+                entries.extend(ordering);
+                expanded.push((entries, output));
+            }
+            if expanded.len() > MAX_TRANSITIONS {
+                return Err(format!("the rule table grows past {} transitions", MAX_TRANSITIONS));
+            }
+        }
+    }
+
+    let words = expanded.len().div_ceil(64);
+    let positions = offsets.len() + 1;
+    let mut matches = vec![vec![0u64; words]; positions * states as usize];
+    let mut outputs = Vec::with_capacity(expanded.len());
+    for (i, (entries, output)) in expanded.iter().enumerate() {
+        for (position, set) in entries.iter().enumerate() {
+            for &state in set {
+                matches[position * states as usize + state as usize][i / 64] |= 1 << (i % 64);
+            }
+        }
+        outputs.push(*output);
+    }
+    let mut table = RuleTable {
+        name,
+        states,
+        offsets,
+        matches,
+        outputs,
+        quiet: 0,
+        colors,
+    };
+    table.quiet = table.lookup(0, &vec![0; offsets.len()]);
+    Ok(table)
+}
+
+// write out the variables of a transition used more than once (or as
+// the output) into one transition per state they can take
+fn bind(inputs: &[Entry], output: &Entry) -> Result<Vec<Transition>, String> {
+    let mut uses: HashMap<&str, usize> = HashMap::new();
+    for entry in inputs.iter().chain(std::iter::once(output)) {
+        if let Some(variable) = &entry.variable {
+            *uses.entry(variable).or_insert(0) += 1;
+        }
+    }
+    if let Some(variable) = &output.variable {
+        if !inputs.iter().any(|e| e.variable.as_ref() == Some(variable)) {
+            return Err(format!("the output variable '{}' isn't an input", variable));
+        }
+    }
+    let mut bound: Vec<&str> = uses.iter().filter(|(_, &n)| n > 1).map(|(&v, _)| v).collect();
+    bound.sort_unstable();
+
+    // step through every choice of states for the bound variables
+    let sets: Vec<&Vec<u8>> = bound
+        .iter()
+        .map(|&v| &inputs.iter().find(|e| e.variable.as_deref() == Some(v)).unwrap().states)
+        .collect();
+    let mut choice = vec![0usize; bound.len()];
+    let mut out = Vec::new();
+    loop {
+        let state_of = |entry: &Entry| -> Vec<u8> {
+            match bound.iter().position(|&v| entry.variable.as_deref() == Some(v)) {
+                Some(i) => vec![sets[i][choice[i]]],
+                None => entry.states.clone(),
+            }
+        };
+        let entries: Vec<Vec<u8>> = inputs.iter().map(state_of).collect();
+        out.push((entries, state_of(output)[0]));
+        if out.len() > MAX_TRANSITIONS {
+            return Err(format!("the rule table grows past {} transitions", MAX_TRANSITIONS));
+        }
+        // the next choice, like counting with a digit per variable
+        let mut i = 0;
+        loop {
+            if i == choice.len() {
+                return Ok(out);
+            }
+            choice[i] += 1;
+            if choice[i] < sets[i].len() {
+                break;
+            }
+            choice[i] = 0;
+            i += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Universe;
+
+    // a table of three states with a single transition: a dead cell
+    // with a 1 and a 2 as its first two neighbours comes alive
+    fn one_transition(neighborhood: &str, symmetries: &str) -> Result<RuleTable, String> {
+        let n = if neighborhood == "hexagonal" { 6 } else { 8 };
+        let zeros = vec!["0"; n - 2].join(",");
+        parse(&format!(
+            "@RULE test\n@TABLE\nn_states:3\nneighborhood:{}\nsymmetries:{}\n0,1,2,{},1\n",
+            neighborhood, symmetries, zeros
+        ))
+    }
+
+    // the (position of the 1, position of the 2) which bring a cell alive
+    fn matched(table: &RuleTable) -> Vec<(usize, usize)> {
+        let n = table.offsets().len();
+        let mut matched = Vec::new();
+        for one in 0..n {
+            for two in (0..n).filter(|&two| two != one) {
+                let mut neighbors = vec![0; n];
+                neighbors[one] = 1;
+                neighbors[two] = 2;
+                if table.next(0, &neighbors) == 1 {
+                    matched.push((one, two));
+                }
+            }
+        }
+        matched
+    }
+
+    #[test]
+    fn symmetries_add_the_turned_and_mirrored_transitions() {
+        // the Moore neighbours go N, NE, E, SE, S, SW, W, NW from 0 to 7,
+        // a quarter turn moves them on by two and the mirror keeps N
+        let moore: [(&str, &[(usize, usize)]); 7] = [
+            ("none", &[(0, 1)]),
+            ("rotate2", &[(0, 1), (4, 5)]),
+            ("rotate4", &[(0, 1), (2, 3), (4, 5), (6, 7)]),
+            ("rotate8", &[(0, 1), (1, 2), (2, 3), (3, 4), (4, 5), (5, 6), (6, 7), (7, 0)]),
+            ("reflect", &[(0, 1), (0, 7)]),
+            (
+                "rotate4reflect",
+                &[(0, 1), (0, 7), (2, 1), (2, 3), (4, 3), (4, 5), (6, 5), (6, 7)],
+            ),
+            ("reflect_horizontal", &[(0, 1), (0, 7)]),
+        ];
+        // and the hexagonal ones go round six
+        let hexagonal: [(&str, &[(usize, usize)]); 3] = [
+            ("rotate3", &[(0, 1), (2, 3), (4, 5)]),
+            ("rotate6", &[(0, 1), (1, 2), (2, 3), (3, 4), (4, 5), (5, 0)]),
+            (
+                "rotate6reflect",
+                &[
+                    (0, 1), (0, 5), (1, 0), (1, 2), (2, 1), (2, 3),
+                    (3, 2), (3, 4), (4, 3), (4, 5), (5, 0), (5, 4),
+                ],
+            ),
+        ];
+        let cases = moore.iter().map(|&(s, m)| ("Moore", s, m));
+        for (neighborhood, symmetries, expected) in
+            cases.chain(hexagonal.iter().map(|&(s, m)| ("hexagonal", s, m)))
+        {
+            let mut expected = expected.to_vec();
+            expected.sort_unstable();
+            let table = one_transition(neighborhood, symmetries).unwrap();
+            assert_eq!(matched(&table), expected, "{} {}", neighborhood, symmetries);
+        }
+        // every turn and mirror of eight neighbours
+        let table = one_transition("Moore", "rotate8reflect").unwrap();
+        assert_eq!(matched(&table).len(), 16);
+        // and permute takes the 1 and the 2 anywhere
+        let table = one_transition("Moore", "permute").unwrap();
+        assert_eq!(matched(&table).len(), 8 * 7);
+    }
+
+    #[test]
+    fn refuses_symmetries_that_dont_fit() {
+        assert!(one_transition("Moore", "rotate3").is_err());
+        assert!(one_transition("hexagonal", "rotate4").is_err());
+        assert!(one_transition("Moore", "spin").is_err());
+    }
+
+    #[test]
+    fn variables_used_twice_take_the_same_state() {
+        let table = parse(
+            "@RULE pairs\n@TABLE\nn_states:3\nneighborhood:vonNeumann\n\
+             var a={1,2}\nvar b={1,2}\n0,a,a,b,0,1\n",
+        )
+        .unwrap();
+        assert_eq!(table.next(0, &[1, 1, 2, 0]), 1);
+        assert_eq!(table.next(0, &[2, 2, 1, 0]), 1);
+        assert_eq!(table.next(0, &[1, 2, 1, 0]), 0);
+        assert_eq!(table.next(0, &[2, 1, 2, 0]), 0);
+    }
+
+    // an electron goes through a diode one way and not the other
+    #[test]
+    fn wireworld_diode_lets_electrons_one_way() {
+        // the tail and head (B, A) at one end of a wire (C), the diode
+        // sits in the middle of it
+        let reaches = |cells: &str, end: u32| {
+            let rle = format!("x = 11, y = 3, rule = WireWorld:P11,3\n{}!", cells);
+            let mut universe = Universe::from_rle(&rle).unwrap();
+            (0..30).any(|_| {
+                universe.tick();
+                universe.get_cells()[(11 + end) as usize].state() == 1
+            })
+        };
+        assert!(reaches("4.2C$BA3C.5C$4.2C", 10));
+        assert!(!reaches("4.2C$5C.3CAB$4.2C", 0));
+        // while a wire without it carries the electron back
+        assert!(reaches("$9CAB", 0));
+    }
+}
@@ -0,0 +1,234 @@
+use crate::{Cell, Neighborhood, Rule, Topology};
+
+// Live neighbour counts worked out a vector of cells at a time, for the
+// rules that only count their live Moore neighbours (Life-like and
+// Generations rules). The live cells of a region are copied out as ones
+// and zeros into a block with a border of one cell, filled in across the
+// joined edges of the topology, and then the count of a row of cells is
+// the sum of the eight rows of that block shifted around it.
+
+// can the rule be stepped from the counts alone
+pub(crate) fn supports(rule: &Rule) -> bool {
+    rule.neighborhood() == Neighborhood::Moore
+        && rule.is_totalistic()
+        && rule.larger().is_none()
+        && rule.table().is_none()
+}
+
+// The number of live neighbours of the cells inside the (top, left,
+// bottom, right) regions of a width x height grid, indexed like the cells.
+// The cells outside of the regions aren't counted and are left at 0.
+pub(crate) fn neighbor_counts(
+    cells: &[Cell],
+    width: u32,
+    height: u32,
+    topology: Topology,
+    regions: &[(u32, u32, u32, u32)],
+) -> Vec<u8> {
+    let w = width as usize;
+    // 1 when the cell at row, col is alive, looked for wherever the
+    // topology puts it past the edge of the grid; past the edge of a plane
+    // it's dead
+    let alive = |row: i64, col: i64| match topology.resolve(row, col, height, width) {
+        Some((r, c)) => (cells[(r * width + c) as usize] == Cell::ALIVE) as u8,
+        None => 0,
+    };
+    let mut counts = vec![0u8; w * height as usize];
+    let mut live = Vec::new();
+    for &(top, left, bottom, right) in regions {
+        if bottom <= top || right <= left {
+            continue;
+        }
+        let (rows, cols) = ((bottom - top) as usize, (right - left) as usize);
+        let padded_width = cols + 2;
+        live.clear();
+        live.resize(padded_width * (rows + 2), 0u8);
+        for pr in 0..rows + 2 {
+            let row = top as i64 + pr as i64 - 1;
+            let line = &mut live[pr * padded_width..(pr + 1) * padded_width];
+            if (0..height as i64).contains(&row) {
+                // the cells of the region are copied straight across
+                let start = row as usize * w + left as usize;
+                let region = &cells[start..start + cols];
+                for (out, &cell) in line[1..=cols].iter_mut().zip(region) {
+                    *out = (cell == Cell::ALIVE) as u8;
+                }
+                line[0] = alive(row, left as i64 - 1);
+                line[cols + 1] = alive(row, right as i64);
+            } else {
+                for (pc, out) in line.iter_mut().enumerate() {
+                    *out = alive(row, left as i64 + pc as i64 - 1);
+                }
+            }
+        }
+        for r in 0..rows {
+            let line = |dr: usize, dc: usize| {
+                let start = (r + dr) * padded_width + dc;
+                &live[start..start + cols]
+            };
+            let sums = [
+                line(0, 0), line(0, 1), line(0, 2),
+                line(1, 0), line(1, 2),
+                line(2, 0), line(2, 1), line(2, 2),
+            ];
+            let start = (top as usize + r) * w + left as usize;
+            add_rows(&mut counts[start..start + cols], &sums);
+        }
+    }
+    counts
+}
+
+// out[i] is the sum of rows[k][i] over the eight rows
+fn add_rows(out: &mut [u8], rows: &[&[u8]; 8]) {
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    {
+        let done = add_rows_simd128(out, rows);
+        add_rows_scalar(&mut out[done..], rows, done);
+    }
+    #[cfg(target_arch = "x86_64")]
+    {
+        let done = if is_x86_feature_detected!("avx2") {
+            // safe as the cpu has just been found to have avx2
+            unsafe { add_rows_avx2(out, rows) }
+        } else {
+            add_rows_sse2(out, rows)
+        };
+        add_rows_scalar(&mut out[done..], rows, done);
+    }
+    #[cfg(not(any(
+        all(target_arch = "wasm32", target_feature = "simd128"),
+        target_arch = "x86_64"
+    )))]
+    add_rows_scalar(out, rows, 0);
+}
+
+// the reference the vector versions have to agree with, summing the rows
+// from the cell at `from` on
+fn add_rows_scalar(out: &mut [u8], rows: &[&[u8]; 8], from: usize) {
+    for (i, out) in out.iter_mut().enumerate() {
+        *out = rows.iter().map(|row| row[from + i]).sum();
+    }
+}
+
+// 16 cells at a time, returning how many cells were summed
+#[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+fn add_rows_simd128(out: &mut [u8], rows: &[&[u8]; 8]) -> usize {
+    use core::arch::wasm32::*;
+    let mut i = 0;
+    while i + 16 <= out.len() {
+        // every row is as long as out, so the loads stay inside them
+        unsafe {
+            let mut sum = u8x16_splat(0);
+            for row in rows {
+                sum = u8x16_add(sum, v128_load(row.as_ptr().add(i) as *const v128));
+            }
+            v128_store(out.as_mut_ptr().add(i) as *mut v128, sum);
+        }
+        i += 16;
+    }
+    i
+}
+
+// 16 cells at a time, sse2 comes with every x86_64 cpu
+#[cfg(target_arch = "x86_64")]
+fn add_rows_sse2(out: &mut [u8], rows: &[&[u8]; 8]) -> usize {
+    use std::arch::x86_64::*;
+    let mut i = 0;
+    while i + 16 <= out.len() {
+        // every row is as long as out, so the loads stay inside them
+        unsafe {
+            let mut sum = _mm_setzero_si128();
+            for row in rows {
+                let cells = _mm_loadu_si128(row.as_ptr().add(i) as *const __m128i);
+                sum = _mm_add_epi8(sum, cells);
+            }
+            _mm_storeu_si128(out.as_mut_ptr().add(i) as *mut __m128i, sum);
+        }
+        i += 16;
+    }
+    i
+}
+
+// 32 cells at a time; only to be called on a cpu with avx2
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn add_rows_avx2(out: &mut [u8], rows: &[&[u8]; 8]) -> usize {
+    use std::arch::x86_64::*;
+    let mut i = 0;
+    while i + 32 <= out.len() {
+        let mut sum = _mm256_setzero_si256();
+        for row in rows {
+            let cells = _mm256_loadu_si256(row.as_ptr().add(i) as *const __m256i);
+            sum = _mm256_add_epi8(sum, cells);
+        }
+        _mm256_storeu_si256(out.as_mut_ptr().add(i) as *mut __m256i, sum);
+        i += 32;
+    }
+    i
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Random;
+
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::wasm_bindgen_test as test;
+
+    // eight rows of len cells with values up to 31, so any lane mixed up
+    // with another shows in the sums
+    fn rows(len: usize, random: &mut Random) -> Vec<Vec<u8>> {
+        (0..8)
+            .map(|_| (0..len).map(|_| (random.next_u64() % 32) as u8).collect())
+            .collect()
+    }
+
+    // A vector adder sums the cells it says it did like the scalar one,
+    // leaving less than a vector of them over; tried on rows of every
+    // length up to a few vectors
+    fn check(adder: impl Fn(&mut [u8], &[&[u8]; 8]) -> usize, lanes: usize) {
+        let mut random = Random::new(lanes as u64);
+        for len in 0..4 * lanes + 3 {
+            let rows = rows(len, &mut random);
+            let rows: [&[u8]; 8] = std::array::from_fn(|k| &rows[k][..]);
+            let mut expected = vec![0; len];
+            add_rows_scalar(&mut expected, &rows, 0);
+            let mut out = vec![0xFF; len];
+            let done = adder(&mut out, &rows);
+            assert!(done <= len && len - done < lanes, "{} of {} cells", done, len);
+            assert_eq!(out[..done], expected[..done], "{} cells", len);
+        }
+    }
+
+    #[test]
+    fn add_rows_sums_like_scalar() {
+        check(
+            |out, rows| {
+                add_rows(out, rows);
+                out.len()
+            },
+            1,
+        );
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn sse2_sums_like_scalar() {
+        check(add_rows_sse2, 16);
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn avx2_sums_like_scalar() {
+        if is_x86_feature_detected!("avx2") {
+            // safe as the cpu has just been found to have avx2
+            check(|out, rows| unsafe { add_rows_avx2(out, rows) }, 32);
+        }
+    }
+
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    #[test]
+    fn simd128_sums_like_scalar() {
+        check(add_rows_simd128, 16);
+    }
+}
@@ -0,0 +1,309 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::grid::Grid;
+use crate::{Cell, PackedGrid, Rule, TickStats, Topology};
+
+// the side of a tile in cells
+const TILE: i64 = 64;
+
+// An unbounded plane keeping only the 64x64 tiles which hold a live (or
+// dying) cell, in a map keyed by (tile row, tile col). The tile at
+// (tr, tc) covers rows tr * 64 .. tr * 64 + 64 and likewise the cols,
+// so rows and cols may be negative. Tiles are added as the pattern
+// spreads into them and dropped once they are empty. Only the tiles near
+// the ones that changed in the last tick are stepped, see `ActiveTiles`.
+pub struct SparseGrid {
+    tiles: HashMap<(i64, i64), Vec<Cell>>,
+    // the tiles which changed in the last tick (or were edited) and
+    // whether the change came within range of their north, south, west
+    // and east sides
+    changed: HashMap<(i64, i64), Edges>,
+}
+
+// the north, south, west and east sides of a tile
+type Edges = (bool, bool, bool, bool);
+
+impl SparseGrid {
+    pub fn new() -> SparseGrid {
+        SparseGrid {
+            tiles: HashMap::new(),
+            changed: HashMap::new(),
+        }
+    }
+
+    // empty space has to stay empty or every tile of the plane fills up
+    pub fn supports(rule: &Rule) -> bool {
+        !rule.has_b0()
+    }
+
+    // a plane holding the cells of a dense width x height grid with its
+    // top left corner at row, col
+    pub fn from_cells(cells: &[Cell], width: u32, height: u32, row: i64, col: i64) -> SparseGrid {
+        let mut sparse = SparseGrid::new();
+        let lines = cells.chunks(width.max(1) as usize).take(height as usize);
+        for (r, line) in lines.enumerate() {
+            for (c, &cell) in line.iter().enumerate().filter(|(_, &c)| c != Cell::DEAD) {
+                sparse.set(row + r as i64, col + c as i64, cell.state());
+            }
+        }
+        sparse
+    }
+
+    // the number of tiles in use
+    pub fn tiles(&self) -> usize {
+        self.tiles.len()
+    }
+
+    // the number of live and dying cells
+    pub fn population(&self) -> u64 {
+        let live = |tile: &Vec<Cell>| tile.iter().filter(|&&c| c != Cell::DEAD).count() as u64;
+        self.tiles.values().map(live).sum()
+    }
+
+    // the (top, left, bottom, right) box around the live and dying
+    // cells, exclusive at the bottom and right
+    pub fn bounding_box(&self) -> Option<(i64, i64, i64, i64)> {
+        let mut bounds: Option<(i64, i64, i64, i64)> = None;
+        for (&(tr, tc), tile) in &self.tiles {
+            for (idx, _) in tile.iter().enumerate().filter(|(_, &c)| c != Cell::DEAD) {
+                let row = tr * TILE + idx as i64 / TILE;
+                let col = tc * TILE + idx as i64 % TILE;
+                bounds = Some(match bounds {
+                    None => (row, col, row + 1, col + 1),
+                    Some((t, l, b, r)) => (t.min(row), l.min(col), b.max(row + 1), r.max(col + 1)),
+                });
+            }
+        }
+        bounds
+    }
+
+    // get the state of the cell at row, col
+    pub fn get(&self, row: i64, col: i64) -> u8 {
+        let key = (row.div_euclid(TILE), col.div_euclid(TILE));
+        match self.tiles.get(&key) {
+            Some(tile) => tile[offset(row, col)].state(),
+            None => 0,
+        }
+    }
+
+    // set the state of the cell at row, col, adding or dropping its tile
+    pub fn set(&mut self, row: i64, col: i64, state: u8) {
+        let key = (row.div_euclid(TILE), col.div_euclid(TILE));
+        if state == 0 && !self.tiles.contains_key(&key) {
+            return;
+        }
+        let tile = self
+            .tiles
+            .entry(key)
+            .or_insert_with(|| vec![Cell::DEAD; (TILE * TILE) as usize]);
+        tile[offset(row, col)] = Cell::new(state);
+        if state == 0 && tile.iter().all(|&c| c == Cell::DEAD) {
+            self.tiles.remove(&key);
+        }
+        // the edit may reach any side of the tile when the rule's range is
+        // long, so all of them are taken to have changed
+        self.changed.insert(key, (true, true, true, true));
+    }
+
+    // take every tile to have changed on all sides, so the next tick
+    // steps all of them and their neighbours, as after the rule changes
+    pub fn mark_all(&mut self) {
+        for &key in self.tiles.keys() {
+            self.changed.insert(key, (true, true, true, true));
+        }
+    }
+
+    // copy the window with its top left corner at row, col into a dense grid
+    pub fn fill_window(&self, row: i64, col: i64, width: u32, height: u32, cells: &mut [Cell]) {
+        self.copy_rect(row, col, width as i64, height as i64, cells);
+    }
+
+    // Step one generation. Every tile that changed, and the tiles around
+    // it the change can reach, is copied out with the cells around it
+    // into a small plane and stepped like a dense grid; the other tiles
+    // stay as they are.
+    pub fn tick(&mut self, rule: &Rule) -> TickStats {
+        let range = rule.larger().map_or(1, |larger| larger.range() as i64);
+        let reach = (range + TILE - 1) / TILE;
+        let mut candidates = HashSet::new();
+        for (&(tr, tc), &(north, south, west, east)) in &self.changed {
+            for dr in -reach..=reach {
+                for dc in -reach..=reach {
+                    let reached = (dr >= 0 || north)
+                        && (dr <= 0 || south)
+                        && (dc >= 0 || west)
+                        && (dc <= 0 || east);
+                    if reached {
+                        candidates.insert((tr + dr, tc + dc));
+                    }
+                }
+            }
+        }
+        let side = TILE + 2 * range;
+        let mut padded = vec![Cell::DEAD; (side * side) as usize];
+        let mut stepped_tiles = Vec::new();
+        let mut evaluated = 0;
+        for &key in &candidates {
+            let (top, left) = (key.0 * TILE - range, key.1 * TILE - range);
+            if !self.copy_rect(top, left, side, side, &mut padded) {
+                continue;
+            }
+            evaluated += 1;
+            let stepped = step(rule, &padded, side as u32);
+            let mut tile = Vec::with_capacity((TILE * TILE) as usize);
+            for r in 0..TILE {
+                let start = ((r + range) * side + range) as usize;
+                tile.extend_from_slice(&stepped[start..start + TILE as usize]);
+            }
+            stepped_tiles.push((key, tile));
+        }
+        // the tiles looked at: those in use and the empty ones a change
+        // could have reached
+        let empty = candidates.iter().filter(|key| !self.tiles.contains_key(key)).count();
+        let stats = TickStats::new((self.tiles.len() + empty) as u32, evaluated);
+        // the new tiles go in once every tile has been stepped from the
+        // old ones
+        self.changed.clear();
+        for (key, tile) in stepped_tiles {
+            let old = self.tiles.get(&key);
+            let before = |i: usize| old.map_or(Cell::DEAD, |old| old[i]);
+            let diff: Vec<usize> = (0..tile.len()).filter(|&i| tile[i] != before(i)).collect();
+            if !diff.is_empty() {
+                self.changed.insert(key, edges(&diff, range));
+            }
+            if tile.iter().any(|&c| c != Cell::DEAD) {
+                self.tiles.insert(key, tile);
+            } else {
+                self.tiles.remove(&key);
+            }
+        }
+        stats
+    }
+
+    // copy a rectangle of the plane into a dense grid a row of a tile at
+    // a time, returning whether any of it was live or dying
+    fn copy_rect(&self, top: i64, left: i64, width: i64, height: i64, cells: &mut [Cell]) -> bool {
+        let mut any = false;
+        for r in 0..height {
+            let line = &mut cells[(r * width) as usize..((r + 1) * width) as usize];
+            let row = top + r;
+            let mut c = 0;
+            while c < width {
+                let col = left + c;
+                // the rest of the row inside this tile
+                let run = (TILE - col.rem_euclid(TILE)).min(width - c);
+                let key = (row.div_euclid(TILE), col.div_euclid(TILE));
+                let out = &mut line[c as usize..(c + run) as usize];
+                match self.tiles.get(&key) {
+                    Some(tile) => {
+                        let start = offset(row, col);
+                        out.copy_from_slice(&tile[start..start + run as usize]);
+                        any |= out.iter().any(|&cell| cell != Cell::DEAD);
+                    }
+                    None => out.fill(Cell::DEAD),
+                }
+                c += run;
+            }
+        }
+        any
+    }
+}
+
+impl Default for SparseGrid {
+    fn default() -> SparseGrid {
+        SparseGrid::new()
+    }
+}
+
+// step a square plane of cells, 64 at a time when the rule allows
+fn step(rule: &Rule, cells: &[Cell], side: u32) -> Vec<Cell> {
+    if PackedGrid::supports(rule, Topology::Plane) {
+        let mut packed = PackedGrid::from_cells(cells, side, side);
+        packed.tick(rule, Topology::Plane);
+        let mut next = vec![Cell::DEAD; cells.len()];
+        packed.unpack(&mut next);
+        return next;
+    }
+    let grid = Grid {
+        cells,
+        width: side,
+        height: side,
+        topology: Topology::Plane,
+    };
+    grid.step(rule)
+}
+
+// whether any of the cells of a tile (by index) lie within range of its
+// north, south, west and east sides
+fn edges(cells: &[usize], range: i64) -> Edges {
+    let (mut north, mut south, mut west, mut east) = (false, false, false, false);
+    for &idx in cells {
+        let (row, col) = (idx as i64 / TILE, idx as i64 % TILE);
+        north |= row < range;
+        south |= row >= TILE - range;
+        west |= col < range;
+        east |= col >= TILE - range;
+    }
+    (north, south, west, east)
+}
+
+// the index of a cell within its tile
+fn offset(row: i64, col: i64) -> usize {
+    (row.rem_euclid(TILE) * TILE + col.rem_euclid(TILE)) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Backend, Universe};
+
+    // a glider heading south east across the corner of four tiles
+    // steps the empty tiles it reaches into, which count as looked at
+    #[test]
+    fn stats_count_the_empty_tiles_stepped() {
+        let mut universe = Universe::new();
+        universe.set_width(128);
+        universe.set_height(128);
+        universe.set_backend(Backend::Sparse).unwrap();
+        universe.load_rle_at(62, 62, "x = 3, y = 3\nbo$2bo$3o!").unwrap();
+        for _ in 0..8 {
+            universe.tick();
+            let stats = universe.stats();
+            assert!(stats.evaluated() <= stats.tiles(), "{:?}", stats);
+            assert_eq!(stats.skipped(), stats.tiles() - stats.evaluated());
+        }
+    }
+
+    // after a rule change the still lifes and quiet tiles step under the
+    // new rule like they do on the dense backend
+    #[test]
+    fn steps_like_dense_after_a_rule_change() {
+        let start = |backend| {
+            let mut universe = Universe::new();
+            universe.set_width(192);
+            universe.set_height(192);
+            universe.set_rule("B3/S23:P").unwrap();
+            // a block and a pond each on a tile of their own, which goes
+            // quiet once they have settled, and a blinker keeping its
+            // tile busy
+            universe.load_rle_at(10, 10, "x = 2, y = 2\n2o$2o!").unwrap();
+            universe.load_rle_at(100, 100, "x = 4, y = 4\nb2o$o2bo$o2bo$b2o!").unwrap();
+            universe.load_rle_at(10, 140, "x = 3, y = 1\n3o!").unwrap();
+            universe.set_backend(backend).unwrap();
+            for _ in 0..4 {
+                universe.tick();
+            }
+            universe
+        };
+        let (mut dense, mut sparse) = (start(Backend::Dense), start(Backend::Sparse));
+        for rule in ["B36/S23", "B3/S12345", "B3/S"] {
+            dense.set_rule(rule).unwrap();
+            sparse.set_rule(rule).unwrap();
+            for generation in 1..=5 {
+                dense.tick();
+                sparse.tick();
+                assert!(dense.get_cells() == sparse.get_cells(), "{} at {}", rule, generation);
+                assert_eq!(dense.population(), sparse.population(), "{}", rule);
+            }
+        }
+    }
+}
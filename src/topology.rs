@@ -0,0 +1,298 @@
+use std::str::FromStr;
+
+// How the edges of the universe are glued together. These follow Golly's
+// bounded grids which are written as a suffix on the rule, ie.
+// "B3/S23:T64,64" for a 64x64 torus or "B3/S23:P64,64" for a plane.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum Topology {
+    // both pairs of edges are joined, a glider leaving at the top
+    // comes back in at the bottom (Golly's "T")
+    #[default]
+    Torus,
+    // nothing is joined, the cells past the edges are always dead (Golly's "P")
+    Plane,
+    // only the left and right edges are joined (Golly's "Tw,0")
+    HorizontalCylinder,
+    // only the top and bottom edges are joined (Golly's "T0,h")
+    VerticalCylinder,
+    // like the torus but one pair of edges is joined with a twist
+    // (Golly's "K"). `twisted_rows` twists the left and right edges
+    // ("Kw,h*"), otherwise the top and bottom edges are twisted ("Kw*,h")
+    KleinBottle { twisted_rows: bool },
+    // both pairs of edges are joined with a twist (Golly's "C")
+    CrossSurface,
+    // the top edge is joined to the left edge and the bottom edge
+    // to the right edge, the universe should be square (Golly's "S")
+    Sphere,
+}
+
+impl Topology {
+    // Map a row/col which may lie outside of the universe back onto the
+    // universe, as far past the edges as the range of the rule reaches.
+    // Returns None when the location is outside of a bounded edge and
+    // should be read as a dead cell.
+    pub fn resolve(&self, row: i64, col: i64, height: u32, width: u32) -> Option<(u32, u32)> {
+        let (h, w) = (height as i64, width as i64);
+        let row_inside = row >= 0 && row < h;
+        let col_inside = col >= 0 && col < w;
+        if row_inside && col_inside {
+            return Some((row as u32, col as u32));
+        }
+        let (row, col) = match self {
+            Topology::Torus => (row.rem_euclid(h), col.rem_euclid(w)),
+            Topology::Plane => return None,
+            Topology::HorizontalCylinder => {
+                if !row_inside {
+                    return None;
+                }
+                (row, col.rem_euclid(w))
+            }
+            Topology::VerticalCylinder => {
+                if !col_inside {
+                    return None;
+                }
+                (row.rem_euclid(h), col)
+            }
+            Topology::KleinBottle { twisted_rows: false } => {
+                // crossing the top or bottom edge mirrors the column, so
+                // crossing it twice on a small grid mirrors it back
+                let col = col.rem_euclid(w);
+                if twisted(row, h) {
+                    (row.rem_euclid(h), w - 1 - col)
+                } else {
+                    (row.rem_euclid(h), col)
+                }
+            }
+            Topology::KleinBottle { twisted_rows: true } => {
+                // crossing the left or right edge mirrors the row
+                let row = row.rem_euclid(h);
+                if twisted(col, w) {
+                    (h - 1 - row, col.rem_euclid(w))
+                } else {
+                    (row, col.rem_euclid(w))
+                }
+            }
+            Topology::CrossSurface => {
+                let (mut r, mut c) = (row.rem_euclid(h), col.rem_euclid(w));
+                if twisted(row, h) {
+                    c = w - 1 - c;
+                }
+                if twisted(col, w) {
+                    r = h - 1 - r;
+                }
+                (r, c)
+            }
+            Topology::Sphere => {
+                // the corners have no sensible partner on a sphere
+                if !row_inside && !col_inside {
+                    return None;
+                }
+                // the distance past an edge carries on in from the edge
+                // it is joined to, which on a small sphere may go past
+                // the edge across from that one. The edges only line up
+                // when the sphere is square, otherwise whatever is still
+                // outside is dead.
+                let (row, col) = if row < 0 {
+                    (col, -1 - row)
+                } else if row >= h {
+                    (col, w - 1 - (row - h))
+                } else if col < 0 {
+                    (-1 - col, row)
+                } else {
+                    (h - 1 - (col - w), row)
+                };
+                if h == w {
+                    return self.resolve(row, col, height, width);
+                }
+                (row, col)
+            }
+        };
+        if row >= 0 && row < h && col >= 0 && col < w {
+            Some((row as u32, col as u32))
+        } else {
+            None
+        }
+    }
+
+    // write the topology out as a Golly bounded grid suffix
+    pub fn to_golly(&self, width: u32, height: u32) -> String {
+        match self {
+            Topology::Torus => format!("T{},{}", width, height),
+            Topology::Plane => format!("P{},{}", width, height),
+            Topology::HorizontalCylinder => format!("T{},0", width),
+            Topology::VerticalCylinder => format!("T0,{}", height),
+            Topology::KleinBottle { twisted_rows: false } => format!("K{}*,{}", width, height),
+            Topology::KleinBottle { twisted_rows: true } => format!("K{},{}*", width, height),
+            Topology::CrossSurface => format!("C{},{}", width, height),
+            Topology::Sphere => format!("S{}", width),
+        }
+    }
+
+    // Parse a Golly bounded grid suffix such as "T64,64", "P", "K30*,20"
+    // or "S50". Besides the topology this returns the width and height
+    // when the suffix names them.
+    pub fn parse_grid(s: &str) -> Result<(Topology, Option<(u32, u32)>), String> {
+        let s = s.trim();
+        let mut chars = s.chars();
+        let letter = chars
+            .next()
+            .ok_or_else(|| "empty topology".to_string())?
+            .to_ascii_uppercase();
+        let dims = chars.as_str();
+        // split the dimensions, keeping note of the twisted one
+        let mut sizes = Vec::new();
+        let mut stars = Vec::new();
+        if !dims.is_empty() {
+            for part in dims.split(',') {
+                let part = part.trim();
+                let starred = part.ends_with('*');
+                let digits = part.trim_end_matches('*');
+                let size = digits
+                    .parse::<u32>()
+                    .map_err(|_| format!("bad size '{}' in topology '{}'", part, s))?;
+                sizes.push(size);
+                stars.push(starred);
+            }
+        }
+        if sizes.len() > 2 {
+            return Err(format!("topology '{}' has too many sizes", s));
+        }
+        let size = match sizes.as_slice() {
+            [] => None,
+            [side] => Some((*side, *side)),
+            [width, height] => Some((*width, *height)),
+            _ => unreachable!(),
+        };
+
+        let topology = match letter {
+            'T' => match size {
+                Some((0, 0)) => return Err("a torus needs at least one wrapped edge".to_string()),
+                Some((_, 0)) => Topology::HorizontalCylinder,
+                Some((0, _)) => Topology::VerticalCylinder,
+                _ => Topology::Torus,
+            },
+            'P' => Topology::Plane,
+            'K' => Topology::KleinBottle {
+                twisted_rows: stars.len() == 2 && stars[1],
+            },
+            'C' => Topology::CrossSurface,
+            'S' => Topology::Sphere,
+            _ => return Err(format!("unknown topology '{}'", s)),
+        };
+        // a zero size means unbounded in Golly, our universes always
+        // have a size so only report it when both are given
+        let size = size.filter(|&(w, h)| w > 0 && h > 0);
+        Ok((topology, size))
+    }
+}
+
+// whether a location wraps an odd number of times past the edges of a
+// side, crossing a twisted edge that many times
+fn twisted(location: i64, side: i64) -> bool {
+    location.div_euclid(side) % 2 != 0
+}
+
+impl FromStr for Topology {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Topology, String> {
+        Topology::parse_grid(s).map(|(topology, _)| topology)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KLEIN: Topology = Topology::KleinBottle { twisted_rows: false };
+    const KLEIN_ROWS: Topology = Topology::KleinBottle { twisted_rows: true };
+
+    #[test]
+    fn parses_golly_grids() {
+        let cases = [
+            ("T64,64", Topology::Torus, Some((64, 64))),
+            ("t10,10", Topology::Torus, Some((10, 10))),
+            ("T64,0", Topology::HorizontalCylinder, None),
+            ("T0,32", Topology::VerticalCylinder, None),
+            ("P", Topology::Plane, None),
+            ("P20,30", Topology::Plane, Some((20, 30))),
+            ("K30*,20", KLEIN, Some((30, 20))),
+            ("K30,20*", KLEIN_ROWS, Some((30, 20))),
+            ("C10,8", Topology::CrossSurface, Some((10, 8))),
+            ("S50", Topology::Sphere, Some((50, 50))),
+        ];
+        for (text, topology, size) in cases {
+            assert_eq!(Topology::parse_grid(text), Ok((topology, size)), "{}", text);
+        }
+        for text in ["", "T0,0", "Q10,10", "T1,2,3", "Tx", "P10,-1"] {
+            assert!(Topology::parse_grid(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn reads_back_what_it_writes() {
+        let topologies = [
+            Topology::Torus,
+            Topology::Plane,
+            Topology::HorizontalCylinder,
+            Topology::VerticalCylinder,
+            KLEIN,
+            KLEIN_ROWS,
+            Topology::CrossSurface,
+            Topology::Sphere,
+        ];
+        for topology in topologies {
+            let text = topology.to_golly(12, 12);
+            assert_eq!(text.parse::<Topology>(), Ok(topology), "{}", text);
+        }
+    }
+
+    #[test]
+    fn resolves_the_cells_past_the_edges() {
+        // (topology, width, height, row, col, where it lands)
+        let cases = [
+            (Topology::Torus, 4, 3, -1, -1, Some((2, 3))),
+            (Topology::Torus, 4, 3, 3, 4, Some((0, 0))),
+            (Topology::Torus, 4, 3, 1, -1, Some((1, 3))),
+            (Topology::Plane, 4, 3, -1, 0, None),
+            (Topology::Plane, 4, 3, 0, 4, None),
+            (Topology::Plane, 4, 3, 2, 3, Some((2, 3))),
+            (Topology::HorizontalCylinder, 4, 3, 1, -1, Some((1, 3))),
+            (Topology::HorizontalCylinder, 4, 3, -1, 1, None),
+            (Topology::VerticalCylinder, 4, 3, -1, 1, Some((2, 1))),
+            (Topology::VerticalCylinder, 4, 3, 1, 4, None),
+            // over the top or bottom of the Klein bottle the column turns
+            // round, over the sides it doesn't
+            (KLEIN, 4, 3, -1, 0, Some((2, 3))),
+            (KLEIN, 4, 3, 3, 1, Some((0, 2))),
+            (KLEIN, 4, 3, 1, -1, Some((1, 3))),
+            (KLEIN, 4, 3, -1, -1, Some((2, 0))),
+            (KLEIN_ROWS, 4, 3, 0, -1, Some((2, 3))),
+            (KLEIN_ROWS, 4, 3, 1, 4, Some((1, 0))),
+            (KLEIN_ROWS, 4, 3, -1, 0, Some((2, 0))),
+            // the cross surface turns round over every edge
+            (Topology::CrossSurface, 4, 3, -1, 0, Some((2, 3))),
+            (Topology::CrossSurface, 4, 3, 0, -1, Some((2, 3))),
+            (Topology::CrossSurface, 4, 3, 1, 4, Some((1, 0))),
+            (Topology::CrossSurface, 4, 3, -1, -1, Some((0, 0))),
+            // the sphere joins the top to the left and the bottom to the
+            // right, and has nothing past its corners
+            (Topology::Sphere, 3, 3, -1, 2, Some((2, 0))),
+            (Topology::Sphere, 3, 3, 1, -1, Some((0, 1))),
+            (Topology::Sphere, 3, 3, 3, 0, Some((0, 2))),
+            (Topology::Sphere, 3, 3, 1, 3, Some((2, 1))),
+            (Topology::Sphere, 3, 3, -2, 1, Some((1, 1))),
+            (Topology::Sphere, 3, 3, -1, -1, None),
+        ];
+        for (topology, width, height, row, col, expected) in cases {
+            assert_eq!(
+                topology.resolve(row, col, height, width),
+                expected,
+                "{}, {} on {:?}",
+                row,
+                col,
+                topology
+            );
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use std::convert::TryFrom;
+
+use wasm_bindgen::prelude::*;
+
+use crate::analysis::bounding_box;
+use crate::{Cell, Engine, Universe};
+
+#[wasm_bindgen]
+impl Universe {
+    // Move the grid to show the width x height window of the plane with
+    // its top left corner at row, col. Only the unbounded backends
+    // (HashLife and sparse) have a plane to move over; the history is
+    // cleared as it holds the old window.
+    pub fn set_viewport(&mut self, row: i32, col: i32) -> Result<(), String> {
+        let (row, col) = (row as i64, col as i64);
+        match &self.engine {
+            Engine::HashLife(hashlife) => {
+                hashlife.fill_window(row, col, self.width, self.height, &mut self.cells)
+            }
+            Engine::Sparse(sparse) => {
+                sparse.fill_window(row, col, self.width, self.height, &mut self.cells)
+            }
+            _ => return Err("only the HashLife and sparse backends have a viewport".to_string()),
+        }
+        self.viewport = (row, col);
+        self.history.clear();
+        self.active.mark_all(self.width, self.height);
+        self.all_cells_replaced();
+        Ok(())
+    }
+    // the row on the plane of the grid's top left cell
+    pub fn viewport_row(&self) -> i32 {
+        self.viewport.0 as i32
+    }
+    // the col on the plane of the grid's top left cell
+    pub fn viewport_col(&self) -> i32 {
+        self.viewport.1 as i32
+    }
+    // The states of a width x height rectangle with its top left corner
+    // at row, col, one byte per cell row by row. On the unbounded backends
+    // this can be anywhere on the plane, otherwise it is measured on the
+    // grid and cells off the grid are dead.
+    pub fn viewport_cells(&self, row: i32, col: i32, width: u32, height: u32) -> Vec<u8> {
+        let (row, col) = (row as i64, col as i64);
+        let mut cells = vec![Cell::DEAD; width as usize * height as usize];
+        match &self.engine {
+            Engine::HashLife(hashlife) => hashlife.fill_window(row, col, width, height, &mut cells),
+            Engine::Sparse(sparse) => sparse.fill_window(row, col, width, height, &mut cells),
+            _ => {
+                for (idx, cell) in cells.iter_mut().enumerate() {
+                    let r = row + (idx / width as usize) as i64;
+                    let c = col + (idx % width as usize) as i64;
+                    if (0..self.height as i64).contains(&r) && (0..self.width as i64).contains(&c) {
+                        *cell = self.cells[self.get_index(r as u32, c as u32)];
+                    }
+                }
+            }
+        }
+        cells.iter().map(|cell| cell.state()).collect()
+    }
+    // The box around every live and dying cell as [top, left, bottom,
+    // right], bottom and right being exclusive, or an empty array when
+    // everything is dead. On the unbounded backends this takes in the
+    // whole plane and not just the grid, so js can follow a pattern
+    // around. Fails once HashLife has taken the pattern further out than
+    // an i32 reaches.
+    pub fn pattern_bounds(&self) -> Result<Vec<i32>, String> {
+        let bounds = match &self.engine {
+            Engine::HashLife(hashlife) => hashlife.bounding_box(),
+            Engine::Sparse(sparse) => sparse.bounding_box(),
+            _ => bounding_box(&self.cells, self.width),
+        };
+        let (top, left, bottom, right) = match bounds {
+            Some(bounds) => bounds,
+            None => return Ok(Vec::new()),
+        };
+        [top, left, bottom, right]
+            .iter()
+            .map(|&edge| {
+                i32::try_from(edge).map_err(|_| {
+                    format!("the pattern reaches row or col {}, past what an i32 holds", edge)
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Backend, Universe};
+
+    #[test]
+    fn bounds_past_an_i32_are_refused() {
+        let glider = "x = 3, y = 3, rule = B3/S23\nbo$2bo$3o!";
+        let mut universe = Universe::from_rle(glider).unwrap();
+        assert_eq!(universe.pattern_bounds(), Ok(vec![0, 0, 3, 3]));
+        universe.set_backend(Backend::HashLife).unwrap();
+        // the glider goes a cell down and right every 4 generations
+        universe.step(10).unwrap();
+        assert_eq!(universe.pattern_bounds(), Ok(vec![256, 256, 259, 259]));
+        universe.step(40).unwrap();
+        assert!(universe.pattern_bounds().is_err());
+        let empty = Universe::from_rle("x = 3, y = 3\n!").unwrap();
+        assert_eq!(empty.pattern_bounds(), Ok(Vec::new()));
+    }
+}
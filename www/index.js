@@ -1,10 +1,7 @@
-import {Universe, Cell} from "wasm-game-of-life";
+import {Universe, Renderer} from "wasm-game-of-life";
 import {memory} from "wasm-game-of-life/wasm_game_of_life_bg";
 // Cell format
 const CELL_SIZE = 5; // px
-const GRID_COLOR = "#CCCCCC";
-const DEAD_COLOR = "#FFFFFF";
-const ALIVE_COLOR = "#000000";
 
 // create a new universe
 const universe = Universe.new();
@@ -12,11 +9,14 @@ const universe = Universe.new();
 const height = universe.height();
 const width = universe.width();
 console.log(height);
+// the renderer draws the cells and the grid lines into an image
+const renderer = Renderer.new(CELL_SIZE, true);
+renderer.render(universe);
 // Get the canvas element to render the universe
 const canvas = document.getElementById("game-of-life-canvas");
 // set the canvas height and width
-canvas.height = (CELL_SIZE + 1) * height + 1;
-canvas.width = (CELL_SIZE + 1) * width + 1;
+canvas.height = renderer.image_height();
+canvas.width = renderer.image_width();
 // get contex of canvas
 const ctx = canvas.getContext("2d");
 // keep track of the animation to enable it play pause
@@ -28,78 +28,31 @@ const renderLoop = () => {
     // each iteration
     //debugger;
     universe.tick();
-    // draw a grid on the canvas
-    drawGrid();
-    // draw respective dead and alive cells on the grid
-    drawCell();
+    // draw the cells that changed with the tick
+    drawChangedCells();
     // update animationId with the current annimation
     animationId = requestAnimationFrame(renderLoop);
 };
 
-const drawGrid = () => {
-    // start the draw
-    ctx.beginPath();
-    ctx.strokeStyle = GRID_COLOR;
-    // draw vertical lines
-    for (let i = 0; i < width; i++) {
-        // keep y=0 and move on x direction (along the width)
-        ctx.moveTo(i * (CELL_SIZE + 1) + 1, 0);
-        // draw a line through y axis from the current position
-        ctx.lineTo(i * (CELL_SIZE + 1) + 1, height * (CELL_SIZE + 1) + 1);
-    }
-    // draw horizontal lines
-    for (let j = 0; j < height; j++) {
-        // keep x=0 and move on y direction (along the height)
-        ctx.moveTo(0, j * (CELL_SIZE + 1) + 1);
-        // draw a line through x axis from the current position
-        ctx.lineTo(width * (CELL_SIZE + 1) + 1, j * (CELL_SIZE + 1) + 1);
-    }
-    // draw the grid
-    ctx.stroke();
-};
-
 /*
 Book para:
 We can directly access WebAssembly's linear memory via memory, 
 which is defined in the raw wasm module wasm_game_of_life_bg. To draw the cells, 
-we get a pointer to the universe's cells, construct a Uint8Array overlaying the cells buffer, 
-iterate over each cell, and draw a white or black rectangle depending on whether the cell is dead or alive, 
-respectively. By working with pointers and overlays, we avoid copying the cells across the 
-boundary on every tick.
+we get a pointer to the renderer's image, construct a Uint8ClampedArray overlaying it
+and put it on the canvas in one go. By working with pointers and overlays,
+we avoid copying the cells across the boundary on every tick.
  */
-// get the idx of flat mem vect
-const getIndex = (row, col) => {
-    return row * width + col;
-};
-const drawCell = () => {
-    // get the pointer to the start of cell vector
-    const cellsPtr = universe.cells();
-    // init a Uint8Array memory buffer and overlay the wasm linear memory on it
-    const cells = new Uint8Array(memory.buffer, cellsPtr, height * width);
-    // start the draw path
-    ctx.beginPath();
-    // now draw the cells according to their status
-    for (let row = 0; row < height; row++) {
-        for (let col = 0; col < width; col++) {
-            // get the cell index
-            const idx = getIndex(row, col);
-            // get the color of the cell corresponds to the state of the cell
-            ctx.fillStyle = cells[idx] === Cell.Dead 
-                                ? DEAD_COLOR 
-                                : ALIVE_COLOR;
-            ctx.fillRect(
-                // x pos
-                row * (CELL_SIZE + 1) + 1,
-                // y pos
-                col * (CELL_SIZE + 1) + 1,
-                // cell width
-                CELL_SIZE,
-                // cell height
-                CELL_SIZE, 
-            );
-        }
-    }
-    ctx.stroke();
+// redraw the cells that changed since the last draw (or all of them when
+// the renderer can't tell) and put the image on the canvas
+const drawChangedCells = () => {
+    renderer.update(universe);
+    const pixels = new Uint8ClampedArray(
+        memory.buffer,
+        renderer.pixels(),
+        renderer.pixels_len(),
+    );
+    const image = new ImageData(pixels, renderer.image_width(), renderer.image_height());
+    ctx.putImageData(image, 0, 0);
 };
 
 // check if the animation is paused
@@ -149,12 +102,10 @@ canvas.addEventListener('click', event => {
     const col = Math.min(Math.floor(canvasLeft / (CELL_SIZE + 1)), width - 5);
 
     universe.toggle_cell(row, col);
-    drawGrid();
-    drawCell();
+    drawChangedCells();
 });
 // initiate the grid, cells and the render loop and run it
-drawGrid();
-drawCell();
+drawChangedCells();
 //requestAnimationFrame(renderLoop);
 play();
 